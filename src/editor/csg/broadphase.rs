@@ -0,0 +1,281 @@
+use bevy::math::bounding::{Aabb3d, Bounded3d, IntersectsVolume};
+use bevy::utils::{HashMap, HashSet};
+
+use crate::prelude::*;
+
+use super::{
+    brush_mesh::{BrushMesh, BRUSH_EPSILON},
+    CsgLeaf,
+};
+
+/// The maximum number of levels of the grid, a level doubles the cell size of the previous one.
+const MAX_LEVEL: u32 = 32;
+
+/// A spatial index over the csg leaves, keyed on the world bounding box of their brush.
+/// Each leaf is stored in the level whose cells are just bigger than it so it touches at most
+/// 8 cells, small and big brushes can be mixed without filling the grid.
+#[derive(Resource)]
+pub struct HierarchicalHashGrid {
+    /// The size of the cells of the first level.
+    cell_size: f32,
+    cells: HashMap<(u32, IVec3), Vec<Entity>>,
+    entries: HashMap<Entity, GridEntry>,
+    /// The number of entries stored in each level.
+    levels: Vec<usize>,
+}
+
+struct GridEntry {
+    aabb: Aabb3d,
+    level: u32,
+}
+
+impl Default for HierarchicalHashGrid {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl HierarchicalHashGrid {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            entries: HashMap::new(),
+            levels: Vec::new(),
+        }
+    }
+
+    /// Insert `entity` in the grid or move it if it was already inserted.
+    pub fn insert(&mut self, entity: Entity, aabb: Aabb3d) {
+        self.remove(entity);
+        let level = self.level_of(&aabb);
+        let (min, max) = self.cell_range(&aabb, level);
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    self.cells
+                        .entry((level, IVec3::new(x, y, z)))
+                        .or_default()
+                        .push(entity);
+                }
+            }
+        }
+        if self.levels.len() <= level as usize {
+            self.levels.resize(level as usize + 1, 0);
+        }
+        self.levels[level as usize] += 1;
+        self.entries.insert(entity, GridEntry { aabb, level });
+    }
+
+    /// Remove `entity` from the grid, returning its bounding box.
+    pub fn remove(&mut self, entity: Entity) -> Option<Aabb3d> {
+        let entry = self.entries.remove(&entity)?;
+        let (min, max) = self.cell_range(&entry.aabb, entry.level);
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let key = (entry.level, IVec3::new(x, y, z));
+                    let Some(cell) = self.cells.get_mut(&key) else {
+                        continue;
+                    };
+                    cell.retain(|other| *other != entity);
+                    if cell.is_empty() {
+                        self.cells.remove(&key);
+                    }
+                }
+            }
+        }
+        self.levels[entry.level as usize] -= 1;
+        Some(entry.aabb)
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entries.contains_key(&entity)
+    }
+
+    /// The bounding box `entity` was inserted with.
+    pub fn aabb(&self, entity: Entity) -> Option<Aabb3d> {
+        self.entries.get(&entity).map(|entry| entry.aabb)
+    }
+
+    /// The entities whose bounding box overlaps or touches `region`.
+    pub fn query_region(&self, region: &Aabb3d) -> Vec<Entity> {
+        let mut found = HashSet::new();
+        for (level, count) in self.levels.iter().enumerate() {
+            let level = level as u32;
+            if *count == 0 {
+                continue;
+            }
+            let (min, max) = self.cell_range(region, level);
+            let size = (max - min).as_i64vec3() + 1;
+            // Big regions are faster to check against every entry of the level.
+            if size.x.saturating_mul(size.y).saturating_mul(size.z) > 8 * *count as i64 {
+                found.extend(
+                    self.entries
+                        .iter()
+                        .filter(|(_, entry)| entry.level == level)
+                        .map(|(entity, _)| *entity),
+                );
+                continue;
+            }
+            for x in min.x..=max.x {
+                for y in min.y..=max.y {
+                    for z in min.z..=max.z {
+                        if let Some(cell) = self.cells.get(&(level, IVec3::new(x, y, z))) {
+                            found.extend(cell.iter().copied());
+                        }
+                    }
+                }
+            }
+        }
+        found
+            .into_iter()
+            .filter(|entity| self.entries[entity].aabb.intersects(region))
+            .collect()
+    }
+
+    /// The other entities whose bounding box overlaps or touches the one of `entity`.
+    pub fn overlapping(&self, entity: Entity) -> Vec<Entity> {
+        let Some(aabb) = self.aabb(entity) else {
+            return Vec::new();
+        };
+        let mut found = self.query_region(&aabb);
+        found.retain(|other| *other != entity);
+        found
+    }
+
+    /// The level whose cells are at least as big as the bounding box.
+    fn level_of(&self, aabb: &Aabb3d) -> u32 {
+        let extent = (aabb.max - aabb.min).max_element();
+        let mut level = 0;
+        let mut size = self.cell_size;
+        while size < extent && level < MAX_LEVEL {
+            size *= 2.0;
+            level += 1;
+        }
+        level
+    }
+
+    /// The first and last cells touched by the bounding box in `level`.
+    fn cell_range(&self, aabb: &Aabb3d, level: u32) -> (IVec3, IVec3) {
+        let size = self.cell_size * 2.0f32.powi(level as i32);
+        (
+            (aabb.min / size).floor().as_ivec3(),
+            (aabb.max / size).floor().as_ivec3(),
+        )
+    }
+}
+
+/// The bounding box of a brush in world space.
+/// It is grown by [`BRUSH_EPSILON`] so brushes touching by a face are reported as overlapping.
+pub fn world_aabb(brush: &BrushMesh, transform: &GlobalTransform) -> Aabb3d {
+    let local = brush.aabb_3d(Vec3::ZERO, Quat::IDENTITY);
+    let (min, max) = (0..8)
+        .map(|corner| {
+            let corner = Vec3::select(
+                BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0),
+                local.max,
+                local.min,
+            );
+            transform.transform_point(corner)
+        })
+        .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), point| {
+            (min.min(point), max.max(point))
+        });
+    Aabb3d {
+        min: min - BRUSH_EPSILON,
+        max: max + BRUSH_EPSILON,
+    }
+}
+
+/// Keep the grid in sync with the leaves as they move or their brush changes.
+pub fn update_broadphase(
+    mut grid: ResMut<HierarchicalHashGrid>,
+    mut brush_events: EventReader<AssetEvent<BrushMesh>>,
+    mut removed: RemovedComponents<CsgLeaf>,
+    brushes: Res<Assets<BrushMesh>>,
+    leaves: Query<(Entity, Ref<GlobalTransform>, Ref<Handle<BrushMesh>>), With<CsgLeaf>>,
+) {
+    for entity in removed.read() {
+        grid.remove(entity);
+    }
+
+    let changed: Vec<AssetId<BrushMesh>> = brush_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } | AssetEvent::Removed { id } => {
+                Some(*id)
+            }
+            _ => None,
+        })
+        .collect();
+    for (entity, transform, brush) in &leaves {
+        if !transform.is_changed() && !brush.is_changed() && !changed.contains(&brush.id()) {
+            continue;
+        }
+        match brushes.get(&*brush) {
+            Some(brush_mesh) if brush_mesh.vertices().next().is_some() => {
+                grid.insert(entity, world_aabb(brush_mesh, &transform));
+            }
+            _ => {
+                grid.remove(entity);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn aabb(min: Vec3, max: Vec3) -> Aabb3d {
+        Aabb3d { min, max }
+    }
+
+    #[test]
+    fn test_overlapping() {
+        let mut grid = HierarchicalHashGrid::new(1.0);
+        let (a, b, c, big) = (
+            Entity::from_raw(0),
+            Entity::from_raw(1),
+            Entity::from_raw(2),
+            Entity::from_raw(3),
+        );
+        grid.insert(a, aabb(Vec3::ZERO, Vec3::ONE));
+        grid.insert(b, aabb(Vec3::splat(0.5), Vec3::splat(1.5)));
+        grid.insert(c, aabb(Vec3::splat(10.0), Vec3::splat(11.0)));
+        grid.insert(big, aabb(Vec3::splat(-100.0), Vec3::new(100.0, 100.0, 0.0)));
+
+        let mut found = grid.overlapping(a);
+        found.sort();
+        assert_eq!(found, vec![b, big]);
+        assert_eq!(grid.overlapping(c), vec![]);
+
+        // Move `c` next to `a`.
+        grid.insert(c, aabb(Vec3::new(1.0, 0.0, 0.0), Vec3::new(2.0, 1.0, 1.0)));
+        let mut found = grid.overlapping(c);
+        found.sort();
+        assert_eq!(found, vec![a, b, big]);
+
+        grid.remove(b);
+        let mut found = grid.overlapping(a);
+        found.sort();
+        assert_eq!(found, vec![c, big]);
+        assert!(!grid.contains(b));
+    }
+
+    #[test]
+    fn test_query_region() {
+        let mut grid = HierarchicalHashGrid::new(1.0);
+        for i in 0..100 {
+            let min = Vec3::new(i as f32 * 2.0, 0.0, 0.0);
+            grid.insert(Entity::from_raw(i), aabb(min, min + 0.5));
+        }
+        let mut found =
+            grid.query_region(&aabb(Vec3::new(9.0, -1.0, -1.0), Vec3::new(14.2, 1.0, 1.0)));
+        found.sort();
+        assert_eq!(found, (5..8).map(Entity::from_raw).collect::<Vec<_>>());
+        let all = grid.query_region(&aabb(Vec3::splat(-1e6), Vec3::splat(1e6)));
+        assert_eq!(all.len(), 100);
+    }
+}
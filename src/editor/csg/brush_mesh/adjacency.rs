@@ -0,0 +1,154 @@
+use super::*;
+
+impl BrushMesh {
+    /// The half edge going the other way in the neighbour polygon.
+    pub fn twin(&self, id: HalfEdgeId) -> Option<HalfEdgeId> {
+        self.get_half_edge(id).twin
+    }
+
+    /// The next half edge in the polygon of `id`.
+    pub fn next(&self, id: HalfEdgeId) -> HalfEdgeId {
+        self.get_half_edge(id).next
+    }
+
+    /// The previous half edge in the polygon of `id`.
+    pub fn prev(&self, id: HalfEdgeId) -> HalfEdgeId {
+        self.get_half_edge(id).prev
+    }
+
+    /// The polygon on the other side of a half edge.
+    pub fn opposite_polygon(&self, id: HalfEdgeId) -> Option<PolygonId> {
+        self.twin(id).map(|twin| self.get_half_edge(twin).polygon)
+    }
+
+    /// The polygons sharing an edge with `polygon`, in the order of its half edges.
+    pub fn polygon_neighbours(&self, polygon: PolygonId) -> Vec<PolygonId> {
+        let mut neighbours = Vec::new();
+        for half_edge in &self.get_polygon(polygon).half_edges {
+            if let Some(neighbour) = self.opposite_polygon(*half_edge) {
+                if !neighbours.contains(&neighbour) {
+                    neighbours.push(neighbour);
+                }
+            }
+        }
+        neighbours
+    }
+
+    /// The outgoing half edges of a vertice ordered by walking around it.
+    /// If the vertice is on a boundary the fan starts at the boundary.
+    pub fn vertice_fan(&self, vertice: VerticeId) -> Vec<HalfEdgeId> {
+        let Some(&start) = self.get_vertice(vertice).out_half_edges.first() else {
+            return Vec::new();
+        };
+        let mut fan = vec![start];
+
+        // Turn around the vertice until we come back to the start or hit a boundary.
+        let mut current = start;
+        while let Some(out) = self.twin(self.prev(current)) {
+            if fan.contains(&out) {
+                return fan;
+            }
+            fan.push(out);
+            current = out;
+        }
+
+        // We hit a boundary, turn the other way from the start.
+        let mut current = start;
+        while let Some(twin) = self.twin(current) {
+            let out = self.next(twin);
+            if fan.contains(&out) {
+                break;
+            }
+            fan.insert(0, out);
+            current = out;
+        }
+        fan
+    }
+
+    /// The polygons around a vertice in the order of [`BrushMesh::vertice_fan`].
+    pub fn vertice_polygons(&self, vertice: VerticeId) -> Vec<PolygonId> {
+        self.vertice_fan(vertice)
+            .into_iter()
+            .map(|half_edge| self.get_half_edge(half_edge).polygon)
+            .collect()
+    }
+
+    /// The vertices connected to `vertice` by an edge.
+    pub fn vertice_neighbours(&self, vertice: VerticeId) -> Vec<VerticeId> {
+        let vertice = self.get_vertice(vertice);
+        let mut neighbours: Vec<VerticeId> = vertice
+            .out_half_edges
+            .iter()
+            .map(|half_edge| self.get_half_edge(*half_edge).end)
+            .collect();
+        for half_edge in &vertice.in_half_edges {
+            let origin = self.get_half_edge(*half_edge).origin;
+            if !neighbours.contains(&origin) {
+                neighbours.push(origin);
+            }
+        }
+        neighbours
+    }
+
+    /// The half edges that don't have a twin.
+    pub fn boundary_edges(&self) -> impl Iterator<Item = HalfEdgeId> + '_ {
+        self.half_edges
+            .iter()
+            .enumerate()
+            .filter(|(_, half_edge)| half_edge.twin.is_none())
+            .map(|(id, _)| id)
+    }
+
+    /// A brush is closed if every half edge has a twin.
+    pub fn is_closed(&self) -> bool {
+        self.boundary_edges().next().is_none()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::csg::Brushable;
+
+    #[test]
+    fn test_cube_adjacency() {
+        let cube = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        assert!(cube.is_closed());
+        for edge in cube.edges() {
+            let twin = cube.twin(edge.id).unwrap();
+            assert_eq!(cube.twin(twin), Some(edge.id));
+            assert_eq!(cube.get_half_edge(twin).origin, edge.end);
+            assert_eq!(cube.get_half_edge(twin).end, edge.origin);
+            assert_ne!(cube.opposite_polygon(edge.id), Some(edge.polygon));
+        }
+        for poly in cube.polygons() {
+            let start = poly.half_edges[0];
+            let (mut next, mut prev) = (start, start);
+            for _ in 0..poly.half_edges.len() {
+                assert_eq!(cube.prev(cube.next(next)), next);
+                next = cube.next(next);
+                prev = cube.prev(prev);
+                assert!(poly.half_edges.contains(&next));
+            }
+            assert_eq!((next, prev), (start, start));
+            assert_eq!(cube.polygon_neighbours(poly.id).len(), 4);
+        }
+        for vertice in cube.vertices() {
+            assert_eq!(cube.vertice_fan(vertice.id).len(), 3);
+            assert_eq!(cube.vertice_polygons(vertice.id).len(), 3);
+            assert_eq!(cube.vertice_neighbours(vertice.id).len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_open_plane() {
+        let plane = Plane3d::new(Vec3::Y).to_default_brush();
+        assert!(!plane.is_closed());
+        let boundary: Vec<HalfEdgeId> = plane.boundary_edges().collect();
+        assert_eq!(boundary, vec![0, 1, 2, 3]);
+        assert!(plane.polygon_neighbours(0).is_empty());
+        for vertice in plane.vertices() {
+            assert_eq!(plane.vertice_fan(vertice.id).len(), 1);
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::fmt;
+
+use bevy::math::DVec3;
+use bevy::utils::HashSet;
+
+use crate::editor::csg::operations::{BrushMeshOperation, Cleanup};
+
+use super::*;
+
+/// The tolerance of [`BRUSH_EPSILON`] in double precision.
+const EPSILON: f64 = BRUSH_EPSILON as f64;
+
+/// Why [`BrushMesh::convex_hull`] couldn't build a brush.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConvexHullError {
+    /// Less than four distinct points were given.
+    NotEnoughPoints,
+    /// All the points lie on a line.
+    Collinear,
+    /// All the points lie on a plane.
+    Flat,
+}
+
+impl fmt::Display for ConvexHullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotEnoughPoints => write!(f, "a convex hull needs at least 4 distinct points"),
+            Self::Collinear => write!(f, "the points are on a line"),
+            Self::Flat => write!(f, "the points are on a plane"),
+        }
+    }
+}
+
+impl std::error::Error for ConvexHullError {}
+
+/// A triangle of the hull being built, wound counter clockwise seen from outside.
+struct HullFace {
+    vertices: [usize; 3],
+    normal: DVec3,
+    offset: f64,
+    /// The points in front of the face not yet in the hull.
+    outside: Vec<usize>,
+    alive: bool,
+}
+
+impl HullFace {
+    fn new(vertices: [usize; 3], points: &[DVec3]) -> Self {
+        let [a, b, c] = vertices.map(|vertice| points[vertice]);
+        let normal = (b - a).cross(c - a).normalize();
+        Self {
+            vertices,
+            normal,
+            offset: normal.dot(a),
+            outside: Vec::new(),
+            alive: true,
+        }
+    }
+
+    fn distance(&self, point: DVec3) -> f64 {
+        self.normal.dot(point) - self.offset
+    }
+
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.vertices;
+        [(a, b), (b, c), (c, a)]
+    }
+}
+
+impl BrushMesh {
+    /// Build the smallest convex brush containing all the `points` with quickhull.
+    /// The triangles of the hull lying on a same plane are merged in a single polygon.
+    pub fn convex_hull(points: &[Vec3]) -> Result<Self, ConvexHullError> {
+        let mut unique: Vec<DVec3> = Vec::with_capacity(points.len());
+        for point in points.iter().map(|point| point.as_dvec3()) {
+            if !unique
+                .iter()
+                .any(|other| other.distance_squared(point) < EPSILON * EPSILON)
+            {
+                unique.push(point);
+            }
+        }
+        let points = unique;
+        if points.len() < 4 {
+            return Err(ConvexHullError::NotEnoughPoints);
+        }
+
+        let mut faces = initial_simplex(&points)?;
+        let initial: HashSet<usize> = faces.iter().flat_map(|face| face.vertices).collect();
+        assign_points(
+            &mut faces,
+            (0..points.len()).filter(|point| !initial.contains(point)),
+            &points,
+        );
+
+        while let Some(face) = faces
+            .iter()
+            .position(|face| face.alive && !face.outside.is_empty())
+        {
+            // Add the point the farthest from the face to the hull.
+            let eye = *faces[face]
+                .outside
+                .iter()
+                .max_by(|a, b| {
+                    let distance = |point: &&usize| faces[face].distance(points[**point]);
+                    distance(a).total_cmp(&distance(b))
+                })
+                .unwrap();
+            let visible: Vec<usize> = (0..faces.len())
+                .filter(|id| faces[*id].alive && faces[*id].distance(points[eye]) > EPSILON)
+                .collect();
+            let visible_edges: HashSet<(usize, usize)> =
+                visible.iter().flat_map(|id| faces[*id].edges()).collect();
+            // The edges between the visible and hidden faces.
+            let horizon: Vec<(usize, usize)> = visible_edges
+                .iter()
+                .filter(|(a, b)| !visible_edges.contains(&(*b, *a)))
+                .copied()
+                .collect();
+
+            let mut orphans = Vec::new();
+            for id in &visible {
+                faces[*id].alive = false;
+                orphans.append(&mut faces[*id].outside);
+            }
+            let first_new = faces.len();
+            for (a, b) in horizon {
+                faces.push(HullFace::new([a, b, eye], &points));
+            }
+            assign_points(
+                &mut faces[first_new..],
+                orphans.into_iter().filter(|point| *point != eye),
+                &points,
+            );
+        }
+
+        let mut brush = BrushMesh::empty();
+        brush.extend_verticies(points.iter().map(|point| point.as_vec3()));
+        for face in faces.iter().filter(|face| face.alive) {
+            let plane = brush.add_plane(face.normal.as_vec3(), points[face.vertices[0]].as_vec3());
+            brush.add_polygon_on_plane(&face.vertices, plane);
+        }
+        Ok(Cleanup::default().apply(&brush))
+    }
+}
+
+/// The first tetrahedron of the hull, made of extreme points.
+fn initial_simplex(points: &[DVec3]) -> Result<Vec<HullFace>, ConvexHullError> {
+    let farthest = |from: &dyn Fn(DVec3) -> f64| {
+        (0..points.len())
+            .max_by(|a, b| from(points[*a]).total_cmp(&from(points[*b])))
+            .unwrap()
+    };
+    let a = farthest(&|point| -point.x);
+    let b = farthest(&|point| point.distance(points[a]));
+    let line = (points[b] - points[a]).normalize();
+    let distance_to_line = |point: DVec3| (point - points[a]).cross(line).length();
+    let c = farthest(&distance_to_line);
+    if distance_to_line(points[c]) < EPSILON {
+        return Err(ConvexHullError::Collinear);
+    }
+    let normal = (points[b] - points[a])
+        .cross(points[c] - points[a])
+        .normalize();
+    let distance_to_plane = |point: DVec3| normal.dot(point - points[a]);
+    let d = farthest(&|point| distance_to_plane(point).abs());
+    if distance_to_plane(points[d]).abs() < EPSILON {
+        return Err(ConvexHullError::Flat);
+    }
+
+    // Wind the base away from the apex so every face points outside.
+    let (b, c) = if distance_to_plane(points[d]) > 0.0 {
+        (c, b)
+    } else {
+        (b, c)
+    };
+    Ok(vec![
+        HullFace::new([a, b, c], points),
+        HullFace::new([a, d, b], points),
+        HullFace::new([b, d, c], points),
+        HullFace::new([c, d, a], points),
+    ])
+}
+
+/// Give each point to the first face it is in front of, points behind all faces are inside.
+fn assign_points(
+    faces: &mut [HullFace],
+    candidates: impl Iterator<Item = usize>,
+    points: &[DVec3],
+) {
+    for point in candidates {
+        if let Some(face) = faces
+            .iter_mut()
+            .find(|face| face.alive && face.distance(points[point]) > EPSILON)
+        {
+            face.outside.push(point);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cube_hull() {
+        let mut points = Vec::new();
+        for corner in 0..8 {
+            points.push(Vec3::new(
+                (corner & 1) as f32,
+                (corner >> 1 & 1) as f32,
+                (corner >> 2 & 1) as f32,
+            ));
+        }
+        // Points inside, on a face and on an edge aren't part of the hull.
+        points.extend([
+            Vec3::splat(0.5),
+            Vec3::new(0.5, 0.5, 1.0),
+            Vec3::new(0.5, 0.0, 0.0),
+        ]);
+        let hull = BrushMesh::convex_hull(&points).unwrap();
+        assert_eq!(hull.validate(), Ok(()));
+        assert_eq!(hull.polygons().count(), 6);
+        assert_eq!(hull.vertices().count(), 8);
+        assert!((hull.volume() - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_sphere_hull() {
+        let points: Vec<Vec3> = (0..200)
+            .map(|i| {
+                // Spread the points with the golden angle.
+                let y = 1.0 - 2.0 * (i as f32 + 0.5) / 200.0;
+                let angle = i as f32 * PI * (3.0 - 5.0f32.sqrt());
+                let radius = (1.0 - y * y).sqrt();
+                Vec3::new(radius * angle.cos(), y, radius * angle.sin())
+            })
+            .collect();
+        let hull = BrushMesh::convex_hull(&points).unwrap();
+        assert_eq!(hull.validate(), Ok(()));
+        assert_eq!(hull.vertices().count(), 200);
+    }
+
+    #[test]
+    fn test_degenerate_hull() {
+        assert_eq!(
+            BrushMesh::convex_hull(&[Vec3::ZERO, Vec3::X, Vec3::Y]).err(),
+            Some(ConvexHullError::NotEnoughPoints)
+        );
+        let line: Vec<Vec3> = (0..5).map(|i| Vec3::X * i as f32).collect();
+        assert_eq!(
+            BrushMesh::convex_hull(&line).err(),
+            Some(ConvexHullError::Collinear)
+        );
+        let flat = [
+            Vec3::ZERO,
+            Vec3::X,
+            Vec3::Z,
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::X * 2.0,
+        ];
+        assert_eq!(
+            BrushMesh::convex_hull(&flat).err(),
+            Some(ConvexHullError::Flat)
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use std::fmt;
+
+use crate::common::geometry::APlane3d;
+use crate::editor::csg::operations::{BrushMeshOperation, ClippedBrush, PlaneClip};
+
+use super::*;
+
+/// Why a topology edit of a [`BrushMesh`] couldn't be done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopologyError {
+    /// The vertice is not a corner of the polygon.
+    VerticeNotInPolygon {
+        polygon: PolygonId,
+        vertice: VerticeId,
+    },
+    /// The vertices are the same or neighbours in the polygon, there is nothing to split.
+    AdjacentVertices(VerticeId, VerticeId),
+    /// The half edge has no twin.
+    BoundaryEdge(HalfEdgeId),
+    /// The edit would leave a degenerate brush.
+    Degenerate,
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::VerticeNotInPolygon { polygon, vertice } => {
+                write!(f, "vertice {vertice} is not in polygon {polygon}")
+            }
+            Self::AdjacentVertices(a, b) => {
+                write!(f, "vertices {a} and {b} are already linked by an edge")
+            }
+            Self::BoundaryEdge(id) => write!(f, "half edge {id} is on the boundary"),
+            Self::Degenerate => write!(f, "the edit would leave a degenerate brush"),
+        }
+    }
+}
+
+impl std::error::Error for TopologyError {}
+
+/// Edits changing the topology of a brush.
+/// The ids of the vertices, polygons and planes are kept but the half edges are rebuilt, the
+/// half edge ids held before an edit must not be used after it.
+impl BrushMesh {
+    /// Add a vertice on an edge at `t` between its origin and its end.
+    /// The vertice is inserted in the polygons on both sides of the edge.
+    pub fn split_edge(&mut self, edge: HalfEdgeId, t: f32) -> VerticeId {
+        let half_edge = self.get_half_edge(edge).clone();
+        let point = self
+            .get_vertice(half_edge.origin)
+            .point
+            .lerp(self.get_vertice(half_edge.end).point, t);
+        let vertice = self.add_vertice(point);
+        self.insert_after(half_edge.polygon, half_edge.origin, vertice);
+        if let Some(twin) = half_edge.twin {
+            let polygon = self.get_half_edge(twin).polygon;
+            self.insert_after(polygon, half_edge.end, vertice);
+        }
+        self.rebuild_half_edges();
+        vertice
+    }
+
+    /// Cut a polygon in two along the diagonal going from `a` to `b`.
+    /// The polygon keeps the part going from `a` to `b` and the new polygon on the same plane
+    /// is returned.
+    pub fn split_polygon(
+        &mut self,
+        polygon: PolygonId,
+        a: VerticeId,
+        b: VerticeId,
+    ) -> Result<PolygonId, TopologyError> {
+        let verticies = &self.get_polygon(polygon).verticies;
+        let len = verticies.len();
+        let position = |vertice: VerticeId| {
+            verticies
+                .iter()
+                .position(|other| *other == vertice)
+                .ok_or(TopologyError::VerticeNotInPolygon { polygon, vertice })
+        };
+        let (start, end) = (position(a)?, position(b)?);
+        let gap = (end + len - start) % len;
+        if gap <= 1 || gap == len - 1 {
+            return Err(TopologyError::AdjacentVertices(a, b));
+        }
+        let first: Vec<VerticeId> = (0..=gap).map(|i| verticies[(start + i) % len]).collect();
+        let second: Vec<VerticeId> = (0..=len - gap)
+            .map(|i| verticies[(end + i) % len])
+            .collect();
+
+        let Polygon { plane, surface, .. } = *self.get_polygon(polygon);
+        let new_polygon = self.polygons.len();
+        self.polygons[polygon].verticies = first;
+        self.polygons.push(Polygon {
+            verticies: second,
+            half_edges: Vec::new(),
+            plane,
+            surface,
+        });
+        self.get_plane_mut(plane).add_polygon(new_polygon);
+        self.rebuild_half_edges();
+        Ok(new_polygon)
+    }
+
+    /// Push a polygon along its normal by `distance`, linking it to its old outline with a
+    /// quad per edge. The new side polygons are returned.
+    pub fn extrude_polygon(&mut self, polygon: PolygonId, distance: f32) -> Vec<PolygonId> {
+        let Polygon { plane, surface, .. } = *self.get_polygon(polygon);
+        let normal = self.get_plane(plane).normal;
+        let old = self.get_polygon(polygon).verticies.clone();
+        let points: Vec<Vec3> = old
+            .iter()
+            .map(|vertice| self.get_vertice(*vertice).point + normal * distance)
+            .collect();
+        let new = self.extend_verticies(points);
+        self.polygons[polygon].verticies = new.clone();
+
+        let len = old.len();
+        let mut sides = Vec::with_capacity(len);
+        for i in 0..len {
+            let j = (i + 1) % len;
+            let side = [old[i], old[j], new[j], new[i]];
+            // The plane is fitted once the polygon is added.
+            let plane = self.add_plane(Vec3::ZERO, Vec3::ZERO);
+            let side_id = self.polygons.len();
+            self.polygons.push(Polygon {
+                verticies: side.to_vec(),
+                half_edges: Vec::new(),
+                plane,
+                surface,
+            });
+            self.get_plane_mut(plane).add_polygon(side_id);
+            self.refit_plane(side_id);
+            sides.push(side_id);
+        }
+        self.refit_plane(polygon);
+        self.rebuild_half_edges();
+        sides
+    }
+
+    /// Cut a convex brush along an edge with a plane going through its two polygons `width` away
+    /// from the edge. The new polygon is returned.
+    /// The brush is rebuilt by the cut so none of the ids are kept.
+    pub fn bevel_edge(&mut self, edge: HalfEdgeId, width: f32) -> Result<PolygonId, TopologyError> {
+        let half_edge = self.get_half_edge(edge);
+        let twin = half_edge.twin.ok_or(TopologyError::BoundaryEdge(edge))?;
+        let normal = self
+            .get_plane(self.get_polygon(half_edge.polygon).plane)
+            .normal;
+        let twin_normal = self
+            .get_plane(self.get_polygon(self.get_half_edge(twin).polygon).plane)
+            .normal;
+        let direction = half_edge.normalized_direction(&self.verticies);
+        // Going inside the polygon perpendicularly to the edge.
+        let inward = normal.cross(direction);
+        let point = self.get_vertice(half_edge.origin).point + inward * width;
+        let normal =
+            Direction3d::new(normal + twin_normal).map_err(|_| TopologyError::Degenerate)?;
+
+        let ClippedBrush {
+            front: Some(_),
+            back: Some(back),
+        } = PlaneClip(APlane3d::new(point, normal)).apply(self)
+        else {
+            return Err(TopologyError::Degenerate);
+        };
+        *self = back;
+        // The cap of the clip is the last polygon.
+        Ok(self.polygons.len() - 1)
+    }
+
+    /// Move a vertice, the planes of its polygons are fitted to their new vertices.
+    /// The polygons the vertice leaves the plane of are split in triangles going to the new point
+    /// so they stay planar, folded outward when the vertice goes out.
+    pub fn move_vertice(&mut self, vertice: VerticeId, point: Vec3) {
+        let leaving: Vec<(PolygonId, f32)> = (0..self.polygons.len())
+            .filter(|polygon| {
+                let poly = &self.polygons[*polygon];
+                poly.verticies.len() > 3 && poly.verticies.contains(&vertice)
+            })
+            .map(|polygon| {
+                let plane = self.get_plane(self.polygons[polygon].plane);
+                (polygon, plane.distance_to(point))
+            })
+            .filter(|(_, distance)| distance.abs() > BRUSH_EPSILON)
+            .collect();
+        for (polygon, distance) in leaving {
+            if distance > 0.0 {
+                self.fan_polygon(polygon, vertice);
+            } else {
+                self.cut_corner(polygon, vertice);
+            }
+        }
+        self.get_vertice_mut(vertice).point = point;
+        self.refit_planes_around(&[vertice]);
+    }
+
+    /// Move all the vertices of a polygon by `offset`.
+    /// The planes of the polygon and its neighbours are fitted to their new vertices.
+    pub fn move_polygon(&mut self, polygon: PolygonId, offset: Vec3) {
+        let verticies = self.get_polygon(polygon).verticies.clone();
+        for vertice in &verticies {
+            self.get_vertice_mut(*vertice).point += offset;
+        }
+        self.refit_planes_around(&verticies);
+    }
+
+    /// Split a polygon in the triangles going from `vertice` to each of its edges.
+    fn fan_polygon(&mut self, polygon: PolygonId, vertice: VerticeId) {
+        let mut rest = polygon;
+        loop {
+            let verticies = &self.get_polygon(rest).verticies;
+            let len = verticies.len();
+            let Some(position) = verticies.iter().position(|other| *other == vertice) else {
+                return;
+            };
+            if len <= 3 {
+                return;
+            }
+            // The polygon keeps the first triangle, the rest is split again.
+            let across = verticies[(position + 2) % len];
+            match self.split_polygon(rest, vertice, across) {
+                Ok(new) => rest = new,
+                Err(_) => return,
+            }
+        }
+    }
+
+    /// Split the triangle of `vertice` and its neighbours off a polygon.
+    fn cut_corner(&mut self, polygon: PolygonId, vertice: VerticeId) {
+        let verticies = &self.get_polygon(polygon).verticies;
+        let len = verticies.len();
+        let Some(position) = verticies.iter().position(|other| *other == vertice) else {
+            return;
+        };
+        let next = verticies[(position + 1) % len];
+        let previous = verticies[(position + len - 1) % len];
+        // The polygon keeps its other corners, which stay on its plane.
+        let _ = self.split_polygon(polygon, next, previous);
+    }
+
+    /// Insert `vertice` in `polygon` right after `after`.
+    fn insert_after(&mut self, polygon: PolygonId, after: VerticeId, vertice: VerticeId) {
+        let verticies = &mut self.polygons[polygon].verticies;
+        if let Some(position) = verticies.iter().position(|other| *other == after) {
+            verticies.insert(position + 1, vertice);
+        }
+    }
+
+    fn refit_planes_around(&mut self, verticies: &[VerticeId]) {
+        let polygons: Vec<PolygonId> = (0..self.polygons.len())
+            .filter(|polygon| {
+                self.polygons[*polygon]
+                    .verticies
+                    .iter()
+                    .any(|vertice| verticies.contains(vertice))
+            })
+            .collect();
+        for polygon in polygons {
+            self.refit_plane(polygon);
+        }
+    }
+
+    /// Fit the plane of a polygon to its vertices.
+    /// The polygon gets its own plane if it was sharing it with other polygons.
+    fn refit_plane(&mut self, polygon: PolygonId) {
+        let poly = &self.polygons[polygon];
+        let normal = poly.winding_normal(&self.verticies).normalize_or_zero();
+        let center = poly
+            .verticies
+            .iter()
+            .map(|vertice| self.get_vertice(*vertice).point)
+            .sum::<Vec3>()
+            / poly.verticies.len() as f32;
+
+        let plane = poly.plane;
+        if self.planes[plane].polygons == [polygon] && self.planes[plane].holes.is_empty() {
+            let plane = self.get_plane_mut(plane);
+            plane.normal = normal;
+            plane.point = center;
+        } else {
+            self.get_plane_mut(plane)
+                .polygons
+                .retain(|other| *other != polygon);
+            let new_plane = self.add_plane(normal, center);
+            self.get_plane_mut(new_plane).add_polygon(polygon);
+            self.get_polygon_mut(polygon).plane = new_plane;
+        }
+    }
+
+    /// Rebuild all the half edges from the vertices of the polygons.
+    fn rebuild_half_edges(&mut self) {
+        self.half_edges.clear();
+        for vertice in &mut self.verticies {
+            vertice.out_half_edges.clear();
+            vertice.in_half_edges.clear();
+        }
+        for polygon in 0..self.polygons.len() {
+            let verticies = std::mem::take(&mut self.polygons[polygon].verticies);
+            self.polygons[polygon].half_edges = self.add_polygon_half_edges(&verticies, polygon);
+            self.polygons[polygon].verticies = verticies;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::csg::Brushable;
+
+    /// The polygon of the cube facing `normal`.
+    fn face(brush: &BrushMesh, normal: Vec3) -> PolygonId {
+        brush
+            .polygons()
+            .find(|poly| brush.get_plane(poly.plane).normal.dot(normal) > 0.99)
+            .unwrap()
+            .id
+    }
+
+    #[test]
+    fn test_split() {
+        let mut brush = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        let vertice = brush.split_edge(0, 0.5);
+        assert_eq!(brush.validate(), Ok(()));
+        assert_eq!(brush.vertice_neighbours(vertice).len(), 2);
+
+        let top = face(&brush, Vec3::Y);
+        let verticies = brush.get_polygon(top).verticies.clone();
+        assert_eq!(
+            brush.split_polygon(top, verticies[0], verticies[1]),
+            Err(TopologyError::AdjacentVertices(verticies[0], verticies[1]))
+        );
+        brush
+            .split_polygon(top, verticies[0], verticies[2])
+            .unwrap();
+        assert_eq!(brush.validate(), Ok(()));
+        assert_eq!(brush.polygons().count(), 7);
+        assert!((brush.volume() - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_extrude() {
+        let mut brush = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        let top = face(&brush, Vec3::Y);
+        let sides = brush.extrude_polygon(top, 1.0);
+        assert_eq!(sides.len(), 4);
+        assert_eq!(brush.validate(), Ok(()));
+        assert!((brush.volume() - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_bevel() {
+        let mut brush = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        let top = face(&brush, Vec3::Y);
+        let edge = brush.get_polygon(top).half_edges[0];
+        let bevel = brush.bevel_edge(edge, 0.25).unwrap();
+        assert_eq!(brush.validate(), Ok(()));
+        assert_eq!(brush.polygons().count(), 7);
+        assert!((brush.volume() - (1.0 - 0.25 * 0.25 * 0.5)).abs() < 1e-4);
+        let normal = brush.get_plane(brush.get_polygon(bevel).plane).normal;
+        assert!(normal.dot(Vec3::Y) > 0.7);
+    }
+
+    #[test]
+    fn test_move() {
+        let mut brush = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        let top = face(&brush, Vec3::Y);
+        brush.move_polygon(top, Vec3::Y * 0.5);
+        assert_eq!(brush.validate(), Ok(()));
+        assert!((brush.volume() - 1.5).abs() < 1e-4);
+
+        let mut tetrahedron =
+            BrushMesh::convex_hull(&[Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z]).unwrap();
+        let apex = tetrahedron
+            .vertices()
+            .find(|vertice| vertice.point == Vec3::Y)
+            .unwrap()
+            .id;
+        tetrahedron.move_vertice(apex, Vec3::new(0.2, 2.0, 0.2));
+        assert_eq!(tetrahedron.validate(), Ok(()));
+        assert!((tetrahedron.volume() - 2.0 / 6.0).abs() < 1e-4);
+
+        // The three quads around a corner of a cube are each split in two, whichever way it goes.
+        for corner_point in [Vec3::splat(0.75), Vec3::splat(0.25)] {
+            let mut brush = Cuboid::from_size(Vec3::ONE).to_default_brush();
+            let corner = brush
+                .vertices()
+                .find(|vertice| vertice.point == Vec3::splat(0.5))
+                .unwrap()
+                .id;
+            brush.move_vertice(corner, corner_point);
+            assert_eq!(brush.validate(), Ok(()));
+            assert_eq!(brush.polygons().count(), 9);
+            let points: Vec<Vec3> = brush.vertices().map(|vertice| vertice.point).collect();
+            let hull = BrushMesh::convex_hull(&points).unwrap();
+            assert!((brush.volume() - hull.volume()).abs() < 1e-4);
+        }
+
+        // Moving a vertice within its planes doesn't split anything.
+        let mut brush = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        let top = face(&brush, Vec3::Y);
+        let edge = brush.get_polygon(top).half_edges[0];
+        let vertice = brush.split_edge(edge, 0.5);
+        let point = brush.get_vertice(vertice).point;
+        let along = brush.get_half_edge(brush.get_polygon(top).half_edges[0]);
+        let direction = along.normalized_direction(&brush.verticies);
+        brush.move_vertice(vertice, point + direction * 0.1);
+        assert_eq!(brush.validate(), Ok(()));
+        assert_eq!(brush.polygons().count(), 6);
+    }
+}
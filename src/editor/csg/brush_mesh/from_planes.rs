@@ -0,0 +1,212 @@
+use std::fmt;
+
+use bevy::math::DVec3;
+
+use crate::common::geometry::APlane3d;
+
+use super::*;
+
+/// Half size of the box used to detect unbounded sets of planes.
+const BOUNDING_HALF_SIZE: f64 = 1e6;
+
+/// The tolerance of [`BRUSH_EPSILON`] in double precision.
+const EPSILON: f64 = BRUSH_EPSILON as f64;
+
+/// Why [`BrushMesh::from_planes`] couldn't build a brush.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FromPlanesError {
+    /// The half spaces don't share any volume.
+    Empty,
+    /// The half spaces don't enclose a finite volume.
+    Unbounded,
+}
+
+impl fmt::Display for FromPlanesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "the planes don't enclose any volume"),
+            Self::Unbounded => write!(f, "the planes enclose an unbounded volume"),
+        }
+    }
+}
+
+impl std::error::Error for FromPlanesError {}
+
+impl BrushMesh {
+    /// Build the convex brush bounded by the half spaces behind `planes`.
+    /// The normals of the planes point outside of the brush.
+    /// Planes that don't touch the brush or that are duplicated are discarded.
+    pub fn from_planes(planes: &[APlane3d]) -> Result<Self, FromPlanesError> {
+        // The corners are computed in double precision to keep the bounding box from eating the
+        // precision of the real corners.
+        let mut half_spaces: Vec<(DVec3, f64)> = Vec::with_capacity(planes.len() + 6);
+        for plane in planes {
+            let normal = plane.normal.as_dvec3();
+            let offset = normal.dot(plane.origin.as_dvec3());
+            let duplicated = half_spaces.iter().any(|(other_normal, other_offset)| {
+                normal.dot(*other_normal) > 1.0 - EPSILON && (offset - other_offset).abs() < EPSILON
+            });
+            if !duplicated {
+                half_spaces.push((normal, offset));
+            }
+        }
+        let nb_planes = half_spaces.len();
+
+        // Close the volume with a big box so unbounded inputs still produce corners.
+        for normal in [DVec3::X, DVec3::NEG_X, DVec3::Y, DVec3::NEG_Y, DVec3::Z, DVec3::NEG_Z] {
+            half_spaces.push((normal, BOUNDING_HALF_SIZE));
+        }
+
+        let points = corners(&half_spaces);
+        if points.is_empty() {
+            return Err(FromPlanesError::Empty);
+        }
+        let touches_bounds = points.iter().any(|point| {
+            half_spaces[nb_planes..]
+                .iter()
+                .any(|(normal, offset)| (normal.dot(*point) - offset).abs() < EPSILON)
+        });
+        if touches_bounds {
+            return Err(FromPlanesError::Unbounded);
+        }
+
+        let mut brush = BrushMesh::empty();
+        let vertices = brush.extend_verticies(points.iter().map(|point| point.as_vec3()));
+        for (normal, offset) in &half_spaces[..nb_planes] {
+            let on_plane: Vec<VerticeId> = vertices
+                .iter()
+                .copied()
+                .filter(|id| (normal.dot(points[*id]) - offset).abs() < EPSILON)
+                .collect();
+            // The plane only touches the brush on a vertice or an edge.
+            if on_plane.len() < 3 {
+                continue;
+            }
+            let polygon = sort_around(&on_plane, &points, *normal);
+            let center = polygon.iter().map(|id| points[*id]).sum::<DVec3>() / polygon.len() as f64;
+            let plane = brush.add_plane(normal.as_vec3(), center.as_vec3());
+            brush.add_polygon_on_plane(&polygon, plane);
+        }
+
+        if brush.polygons.len() < 4 {
+            return Err(FromPlanesError::Empty);
+        }
+        Ok(brush)
+    }
+}
+
+/// The deduplicated intersection points of every three planes that are inside all the half spaces.
+fn corners(half_spaces: &[(DVec3, f64)]) -> Vec<DVec3> {
+    let mut points: Vec<DVec3> = Vec::new();
+    let len = half_spaces.len();
+    for i in 0..len {
+        for j in (i + 1)..len {
+            for k in (j + 1)..len {
+                let Some(point) = intersect_planes(half_spaces[i], half_spaces[j], half_spaces[k])
+                else {
+                    continue;
+                };
+                let inside = half_spaces
+                    .iter()
+                    .all(|(normal, offset)| normal.dot(point) - offset < EPSILON);
+                let known = points
+                    .iter()
+                    .any(|other| other.distance_squared(point) < EPSILON * EPSILON);
+                if inside && !known {
+                    points.push(point);
+                }
+            }
+        }
+    }
+    points
+}
+
+/// The point shared by three planes `normal . x = offset`.
+fn intersect_planes(
+    (n1, d1): (DVec3, f64),
+    (n2, d2): (DVec3, f64),
+    (n3, d3): (DVec3, f64),
+) -> Option<DVec3> {
+    let det = n1.dot(n2.cross(n3));
+    if det.abs() < EPSILON {
+        return None;
+    }
+    Some((d1 * n2.cross(n3) + d2 * n3.cross(n1) + d3 * n1.cross(n2)) / det)
+}
+
+/// Sort vertices lying on a plane counter clockwise around its normal.
+fn sort_around(vertices: &[VerticeId], points: &[DVec3], normal: DVec3) -> Vec<VerticeId> {
+    let center = vertices.iter().map(|id| points[*id]).sum::<DVec3>() / vertices.len() as f64;
+    let (u, v) = normal.any_orthonormal_pair();
+    let angle = |id: &VerticeId| {
+        let offset = points[*id] - center;
+        offset.dot(v).atan2(offset.dot(u))
+    };
+    let mut sorted = vertices.to_vec();
+    sorted.sort_by(|a, b| angle(a).total_cmp(&angle(b)));
+    sorted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The planes of an axis aligned box.
+    fn box_planes(half_size: f32) -> Vec<APlane3d> {
+        [
+            Vec3::X,
+            Vec3::NEG_X,
+            Vec3::Y,
+            Vec3::NEG_Y,
+            Vec3::Z,
+            Vec3::NEG_Z,
+        ]
+        .map(|normal| APlane3d::new(normal * half_size, Direction3d::new(normal).unwrap()))
+        .to_vec()
+    }
+
+    #[test]
+    fn test_cube_from_planes() {
+        let mut planes = box_planes(1.0);
+        let cube = BrushMesh::from_planes(&planes).unwrap();
+        assert_eq!(cube.validate(), Ok(()));
+        assert_eq!(cube.vertices().count(), 8);
+        assert_eq!(cube.polygons().count(), 6);
+        assert!((cube.volume() - 8.0).abs() < 1e-4);
+
+        // A plane outside of the cube doesn't add a polygon.
+        planes.push(APlane3d::new(Vec3::X * 5.0, Direction3d::X));
+        let cube = BrushMesh::from_planes(&planes).unwrap();
+        assert_eq!(cube.validate(), Ok(()));
+        assert_eq!(cube.polygons().count(), 6);
+    }
+
+    #[test]
+    fn test_large_brush() {
+        let planes = box_planes(1e5);
+        let brush = BrushMesh::from_planes(&planes).unwrap();
+        assert_eq!(brush.validate(), Ok(()));
+        assert_eq!(brush.vertices().count(), 8);
+    }
+
+    #[test]
+    fn test_from_planes_errors() {
+        let planes = box_planes(1.0);
+        assert_eq!(
+            BrushMesh::from_planes(&planes[..5]).err(),
+            Some(FromPlanesError::Unbounded)
+        );
+        assert_eq!(
+            BrushMesh::from_planes(&[]).err(),
+            Some(FromPlanesError::Unbounded)
+        );
+
+        // A half space on the other side of the cube.
+        let mut disjoint = planes.clone();
+        disjoint.push(APlane3d::new(Vec3::X * 2.0, Direction3d::NEG_X));
+        assert_eq!(
+            BrushMesh::from_planes(&disjoint).err(),
+            Some(FromPlanesError::Empty)
+        );
+    }
+}
@@ -21,6 +21,13 @@ impl BrushMesh {
             current_edge: 0,
         }
     }
+
+    pub fn planes(&self) -> BrushPlanes {
+        BrushPlanes {
+            brush: self,
+            current_plane: 0,
+        }
+    }
 }
 
 pub struct BrushVerticies<'b> {
@@ -1,13 +1,27 @@
 use std::array;
 
+mod adjacency;
+mod convex_hull;
+mod edit;
+mod from_planes;
 mod iter;
 //mod iter_mut;
+mod surface;
+mod validate;
 
 use bevy::math::bounding::Bounded3d;
+pub use convex_hull::*;
+pub use edit::*;
+pub use from_planes::*;
 pub use iter::*;
+pub use surface::*;
+pub use validate::*;
 
 use crate::prelude::*;
 
+/// The tolerance used for the geometric tests on brushes.
+pub const BRUSH_EPSILON: f32 = 1e-4;
+
 pub type VerticeId = usize;
 
 #[derive(Reflect, Clone)]
@@ -33,6 +47,14 @@ impl Vertice {
     pub fn add_in_edge(&mut self, id: HalfEdgeId) {
         self.in_half_edges.push(id);
     }
+
+    pub fn out_half_edges(&self) -> &[HalfEdgeId] {
+        &self.out_half_edges
+    }
+
+    pub fn in_half_edges(&self) -> &[HalfEdgeId] {
+        &self.in_half_edges
+    }
 }
 
 pub type HalfEdgeId = usize;
@@ -43,6 +65,13 @@ pub struct HalfEdge {
     pub origin: VerticeId,
     pub end: VerticeId,
     pub polygon: PolygonId,
+    /// The half edge going the other way in the neighbour polygon.
+    /// It is `None` if the edge is on the boundary of the brush.
+    pub twin: Option<HalfEdgeId>,
+    /// The next half edge in the polygon.
+    pub next: HalfEdgeId,
+    /// The previous half edge in the polygon.
+    pub prev: HalfEdgeId,
 }
 
 impl HalfEdge {
@@ -83,6 +112,19 @@ impl Plane {
         self.holes.push(hole);
     }
 
+    pub fn polygons(&self) -> &[PolygonId] {
+        &self.polygons
+    }
+
+    pub fn holes(&self) -> &[PolygonId] {
+        &self.holes
+    }
+
+    /// The signed distance from the plane to a point, positive in front of the plane.
+    pub fn distance_to(&self, point: Vec3) -> f32 {
+        (point - self.point).dot(self.normal)
+    }
+
 }
 
 pub type PolygonId = usize;
@@ -92,29 +134,43 @@ pub struct Polygon {
     pub verticies: Vec<VerticeId>,
     pub half_edges: Vec<HalfEdgeId>,
     pub plane: PlaneId,
+    pub surface: Surface,
 }
 
 impl Polygon {
-    fn is_on_plane(&self, edges: &[HalfEdge], planes: &[Plane], vertices: &[Vertice]) -> bool {
-        let normal = planes[self.plane].normal;
-        for edge in &self.half_edges {
-            let edge = &edges[*edge];
-            let dir = edge.direction(vertices);
-            if dir.cross(normal).length_squared() < f32::EPSILON {
-                continue;
-            } else {
-                return false;
-            }
-        }
-        true
+    /// The normal of the polygon given by its winding, computed with Newell's method.
+    /// It is not normalized, its length is twice the area of the polygon.
+    fn winding_normal(&self, vertices: &[Vertice]) -> Vec3 {
+        let len = self.verticies.len();
+        (0..len).fold(Vec3::ZERO, |normal, i| {
+            let current = vertices[self.verticies[i]].point;
+            let next = vertices[self.verticies[(i + 1) % len]].point;
+            normal + current.cross(next)
+        })
+    }
+
+    /// Returns the first vertice of the polygon that is not on its plane.
+    fn off_plane_vertice(&self, planes: &[Plane], vertices: &[Vertice]) -> Option<VerticeId> {
+        let plane = &planes[self.plane];
+        self.verticies.iter().copied().find(|vertice| {
+            plane.distance_to(vertices[*vertice].point).abs() > BRUSH_EPSILON
+        })
+    }
+
+    fn is_on_plane(&self, planes: &[Plane], vertices: &[Vertice]) -> bool {
+        self.off_plane_vertice(planes, vertices).is_none()
     }
 
-    fn is_convex(&self, edges: &[HalfEdge], vertices: &[Vertice]) -> bool {
+    fn is_convex(&self, edges: &[HalfEdge], planes: &[Plane], vertices: &[Vertice]) -> bool {
+        let normal = planes[self.plane].normal;
         let nb_edges = self.half_edges.len();
-        for edge in 0..nb_edges {
-            let cur = edges[edge].direction(vertices).flip();
-            let next = edges[(edge + 1) % nb_edges].direction(vertices);
-            if (next.angle_between(cur).abs() - 180.0).abs() > f32::EPSILON {
+        for i in 0..nb_edges {
+            let cur = edges[self.half_edges[i]].direction(vertices).normalize_or_zero();
+            let next = edges[self.half_edges[(i + 1) % nb_edges]]
+                .direction(vertices)
+                .normalize_or_zero();
+            // Turning clockwise around the normal means a reflex vertice.
+            if cur.cross(next).dot(normal) < -BRUSH_EPSILON {
                 return false;
             }
         }
@@ -156,12 +212,12 @@ impl BrushMesh {
         let start = self.verticies.len();
         self.verticies
             .extend(points.into_iter().map(Vertice::from_point));
-        (start..(self.verticies.len() - 1)).collect()
+        (start..self.verticies.len()).collect()
     }
 
     pub fn add_plane(&mut self, normal: Vec3, point: Vec3) -> PlaneId {
-        self.planes.push(Plane::new(normal, point));
-        self.planes.len()
+        self.planes.push(Plane::new(point, normal));
+        self.planes.len() - 1
     }
 
     pub fn add_planes<const N: usize>(&mut self, planes: [Plane; N]) -> [PlaneId; N] {
@@ -170,10 +226,10 @@ impl BrushMesh {
         array::from_fn(|i| start + i)
     }
 
-    pub fn extend_planes(&mut self, planes: impl IntoIterator<Item = Plane>) -> Vec<VerticeId> {
+    pub fn extend_planes(&mut self, planes: impl IntoIterator<Item = Plane>) -> Vec<PlaneId> {
         let start = self.planes.len();
-        self.planes.extend(planes.into_iter());
-        (start..(self.verticies.len() - 1)).collect()
+        self.planes.extend(planes);
+        (start..self.planes.len()).collect()
     }
 
     pub fn get_vertice(&self, id: VerticeId) -> &Vertice {
@@ -200,37 +256,44 @@ impl BrushMesh {
         &self.polygons[id]
     }
 
+    pub fn set_polygon_surface(&mut self, id: PolygonId, surface: Surface) {
+        self.get_polygon_mut(id).surface = surface;
+    }
+
     pub fn add_polygon_on_plane(&mut self, vertices: &[VerticeId], plane: PlaneId) -> PolygonId {
         assert!(
             vertices.len() >= 3,
             "Tried to construct a polygon of length less than 3."
         );
         let polygon_id = self.polygons.len();
-        let mut half_edges = Vec::with_capacity(vertices.len());
-        let prev_vertice_id = vertices[0];
-        vertices
-            .iter()
-            .cycle()
-            .skip(1)
-            .take(vertices.len() - 1)
-            .for_each(|vertice_id| {
-                let half_edge_id = self.half_edges.len();
-                half_edges.push(half_edge_id);
-                let prev_vertice = self.get_vertice_mut(prev_vertice_id);
-                prev_vertice.out_half_edges.push(half_edge_id);
-                let vertice = self.get_vertice_mut(*vertice_id);
-                vertice.in_half_edges.push(half_edge_id);
-                self.half_edges.push(HalfEdge {
-                    origin: prev_vertice_id,
-                    end: *vertice_id,
-                    polygon: polygon_id,
-                });
-            });
+        let half_edges = self.add_polygon_half_edges(vertices, polygon_id);
+        self.get_plane_mut(plane).add_polygon(polygon_id);
 
         self.polygons.push(Polygon {
             half_edges,
             verticies: vertices.into(),
             plane,
+            surface: Surface::default(),
+        });
+        polygon_id
+    }
+
+    /// Add a hole in the polygons of `plane`, going clockwise around its normal.
+    /// The hole is only a border of the polygon around it and isn't triangulated.
+    pub fn add_hole_on_plane(&mut self, vertices: &[VerticeId], plane: PlaneId) -> PolygonId {
+        assert!(
+            vertices.len() >= 3,
+            "Tried to construct a hole of length less than 3."
+        );
+        let polygon_id = self.polygons.len();
+        let half_edges = self.add_polygon_half_edges(vertices, polygon_id);
+        self.get_plane_mut(plane).add_hole(polygon_id);
+
+        self.polygons.push(Polygon {
+            half_edges,
+            verticies: vertices.into(),
+            plane,
+            surface: Surface::default(),
         });
         polygon_id
     }
@@ -244,59 +307,109 @@ impl BrushMesh {
             "Tried to construct a polygon of length less than 3."
         );
         let polygon_id = self.polygons.len();
-        let mut half_edges = Vec::with_capacity(vertices.len());
+        let half_edges = self.add_polygon_half_edges(vertices, polygon_id);
 
-        // Construct the half edges if they don't exist.
-        let mut plane_center = Vec3::ZERO;
-        let prev_vertice_id = vertices[0];
-        vertices
+        let plane_center = vertices
             .iter()
-            .cycle()
-            .skip(1)
-            .take(vertices.len() - 1)
-            .for_each(|vertice_id| {
-                let half_edge_id = self.half_edges.len();
-                half_edges.push(half_edge_id);
-                let prev_vertice = self.get_vertice_mut(prev_vertice_id);
-                prev_vertice.out_half_edges.push(half_edge_id);
-                plane_center += prev_vertice.point * scaling_factor;
-                let vertice = self.get_vertice_mut(*vertice_id);
-                vertice.in_half_edges.push(half_edge_id);
-
-                self.half_edges.push(HalfEdge {
-                    origin: prev_vertice_id,
-                    end: *vertice_id,
-                    polygon: polygon_id,
-                });
-            });
+            .map(|vertice| self.get_vertice(*vertice).point)
+            .sum::<Vec3>()
+            / scaling_factor;
 
         // Compute plane Normal.
         let plane_normal = {
             let a = self.get_vertice(vertices[0]).point;
             let b = self.get_vertice(vertices[1]).point;
             let c = self.get_vertice(vertices[2]).point;
-            (b - a).cross(c - a)
+            (b - a).cross(c - a).normalize_or_zero()
         };
         let plane = self.add_plane(plane_normal, plane_center);
+        self.get_plane_mut(plane).add_polygon(polygon_id);
 
         self.polygons.push(Polygon {
             half_edges,
             verticies: vertices.into(),
             plane,
+            surface: Surface::default(),
         });
         polygon_id
     }
 
+    /// Construct the half edges of a polygon going around `vertices`.
+    /// The half edges are linked to each other and to their twin if it already exists.
+    fn add_polygon_half_edges(
+        &mut self,
+        vertices: &[VerticeId],
+        polygon: PolygonId,
+    ) -> Vec<HalfEdgeId> {
+        let start = self.half_edges.len();
+        let len = vertices.len();
+        for (i, &origin) in vertices.iter().enumerate() {
+            let half_edge_id = start + i;
+            let end = vertices[(i + 1) % len];
+
+            let twin = self.find_unpaired_half_edge(end, origin);
+            if let Some(twin) = twin {
+                self.get_half_edge_mut(twin).twin = Some(half_edge_id);
+            }
+            self.get_vertice_mut(origin).add_out_edge(half_edge_id);
+            self.get_vertice_mut(end).add_in_edge(half_edge_id);
+
+            self.half_edges.push(HalfEdge {
+                origin,
+                end,
+                polygon,
+                twin,
+                next: start + (i + 1) % len,
+                prev: start + (i + len - 1) % len,
+            });
+        }
+        (start..start + len).collect()
+    }
+
+    /// Find a half edge going from `origin` to `end` that doesn't have a twin yet.
+    fn find_unpaired_half_edge(&self, origin: VerticeId, end: VerticeId) -> Option<HalfEdgeId> {
+        self.get_vertice(origin)
+            .out_half_edges
+            .iter()
+            .copied()
+            .find(|id| {
+                let half_edge = self.get_half_edge(*id);
+                half_edge.end == end && half_edge.twin.is_none()
+            })
+    }
 
     pub fn positions(&self) -> Vec<Vec3> {
         self.verticies.iter().map(|v| v.point).collect()
     }
 
+    /// The average of the vertices of the brush.
+    pub fn center(&self) -> Vec3 {
+        if self.verticies.is_empty() {
+            return Vec3::ZERO;
+        }
+        self.verticies.iter().map(|vertice| vertice.point).sum::<Vec3>()
+            / self.verticies.len() as f32
+    }
+
+    /// The volume enclosed by the brush, only meaningful for closed brushes.
+    pub fn volume(&self) -> f32 {
+        let mut volume = 0.0;
+        for polygon in &self.polygons {
+            let first = self.get_vertice(polygon.verticies[0]).point;
+            for pair in polygon.verticies[1..].windows(2) {
+                let b = self.get_vertice(pair[0]).point;
+                let c = self.get_vertice(pair[1]).point;
+                volume += first.dot(b.cross(c));
+            }
+        }
+        volume / 6.0
+    }
+
     fn get_plane_mut(&mut self, id: PlaneId) -> &mut Plane {
         &mut self.planes[id]
     }
 
-    fn get_polygon_mut(&mut self, id: PlaneId) -> &mut Polygon {
+    fn get_polygon_mut(&mut self, id: PolygonId) -> &mut Polygon {
         &mut self.polygons[id]
     }
 }
@@ -306,7 +419,7 @@ impl Bounded3d for BrushMesh {
         let (min, max) =
             self.verticies
                 .iter()
-                .fold((Vec3::NEG_INFINITY, Vec3::INFINITY), |state, next| {
+                .fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |state, next| {
                     let next = rotation * next.point + translation;
                     (state.0.min(next), state.1.max(next))
                 });
@@ -323,10 +436,16 @@ impl Bounded3d for BrushMesh {
         let radius = self
             .verticies
             .iter()
-            .fold(0.0, |r: f32, v| r.max(v.point.length()));
+            .fold(0.0, |r: f32, v| r.max((rotation * v.point + translation).distance(center)));
         bevy::math::bounding::BoundingSphere {
             center,
             sphere: Sphere { radius },
         }
     }
 }
+
+impl AsRef<BrushMesh> for BrushMesh {
+    fn as_ref(&self) -> &BrushMesh {
+        self
+    }
+}
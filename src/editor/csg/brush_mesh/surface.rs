@@ -0,0 +1,221 @@
+use super::*;
+
+/// How a polygon looks.
+#[derive(Reflect, Default, Clone, Copy, PartialEq, Debug)]
+pub struct Surface {
+    pub texture: TextureProjection,
+    /// The default material is used when none is set.
+    pub material: Option<BrushMaterial>,
+    /// Polygons of the same group share their normals along their common edges.
+    pub smoothing_group: Option<u32>,
+}
+
+/// The color sets of `assets/textures`.
+#[derive(Reflect, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TextureColor {
+    Black,
+    Blue,
+    #[default]
+    Dark,
+    Green,
+    Light,
+    LightV2,
+    Orange,
+    Pink,
+    Red,
+    Teal,
+    Yellow,
+}
+
+impl TextureColor {
+    pub fn folder(&self) -> &'static str {
+        match self {
+            Self::Black => "Black",
+            Self::Blue => "Blue",
+            Self::Dark => "Dark",
+            Self::Green => "Green",
+            Self::Light => "Light",
+            Self::LightV2 => "Light (v2)",
+            Self::Orange => "Orange",
+            Self::Pink => "Pink",
+            Self::Red => "Red",
+            Self::Teal => "Teal",
+            Self::Yellow => "Yellow",
+        }
+    }
+}
+
+/// A material made from one of the textures of `assets/textures/<color>/tex_<index>.png`.
+#[derive(Reflect, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BrushMaterial {
+    pub color: TextureColor,
+    /// The number of the texture, from 1.
+    pub index: u8,
+}
+
+impl BrushMaterial {
+    pub fn new(color: TextureColor, index: u8) -> Self {
+        Self { color, index }
+    }
+
+    /// The path of the texture in the assets.
+    pub fn texture_path(&self) -> String {
+        format!("textures/{}/tex_{}.png", self.color.folder(), self.index)
+    }
+}
+
+/// The axis a texture is projected along.
+#[derive(Reflect, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProjectionAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl ProjectionAxis {
+    /// The axis closest to `normal`.
+    pub fn closest(normal: Vec3) -> Self {
+        let normal = normal.abs();
+        if normal.x >= normal.y && normal.x >= normal.z {
+            Self::X
+        } else if normal.y >= normal.z {
+            Self::Y
+        } else {
+            Self::Z
+        }
+    }
+
+    pub fn as_vec3(&self) -> Vec3 {
+        match self {
+            Self::X => Vec3::X,
+            Self::Y => Vec3::Y,
+            Self::Z => Vec3::Z,
+        }
+    }
+}
+
+/// How the texture is laid on a polygon.
+#[derive(Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureAlignment {
+    /// Projected along the world axis closest to the normal of the polygon, the textures line up
+    /// across brushes.
+    #[default]
+    World,
+    /// Projected along a fixed axis.
+    Axis(ProjectionAxis),
+    /// Laid on the plane of the polygon, the texture follows the slope of the face.
+    Face,
+}
+
+/// The planar projection giving the texture coordinates of a polygon.
+#[derive(Reflect, Clone, Copy, PartialEq, Debug)]
+pub struct TextureProjection {
+    pub alignment: TextureAlignment,
+    /// The size in world units covered by the texture once.
+    pub scale: Vec2,
+    /// Moves the texture on the polygon, in texture units.
+    pub offset: Vec2,
+    /// Turns the texture on the polygon, in radians.
+    pub rotation: f32,
+}
+
+impl Default for TextureProjection {
+    fn default() -> Self {
+        Self {
+            alignment: TextureAlignment::World,
+            scale: Vec2::ONE,
+            offset: Vec2::ZERO,
+            rotation: 0.0,
+        }
+    }
+}
+
+impl TextureProjection {
+    /// The world directions of the u and v axes of the texture on a polygon facing `normal`,
+    /// before rotation and scale.
+    /// Seen from the front of the polygon u goes right and v goes down.
+    pub fn axes(&self, normal: Vec3) -> (Vec3, Vec3) {
+        let facing = match self.alignment {
+            TextureAlignment::World => ProjectionAxis::closest(normal).as_vec3(),
+            TextureAlignment::Axis(axis) => axis.as_vec3(),
+            TextureAlignment::Face => normal,
+        };
+        // Keep the texture from being mirrored on the back facing sides.
+        let facing = facing * facing.dot(normal).signum();
+        let up = if facing.y.abs() > 1.0 - BRUSH_EPSILON {
+            Vec3::NEG_Z
+        } else {
+            Vec3::Y
+        };
+        let u = up.cross(facing).normalize_or_zero();
+        (u, u.cross(facing))
+    }
+
+    /// The texture coordinates of a `point` of a polygon facing `normal`.
+    pub fn uv(&self, point: Vec3, normal: Vec3) -> Vec2 {
+        let (u, v) = self.axes(normal);
+        let planar = Vec2::new(point.dot(u), point.dot(v));
+        Vec2::from_angle(self.rotation).rotate(planar) / self.scale + self.offset
+    }
+
+    /// The tangent of a polygon facing `normal`, going along increasing u with the sign of the
+    /// bitangent in w.
+    pub fn tangent(&self, normal: Vec3) -> Vec4 {
+        let (u, v) = self.axes(normal);
+        let (sin, cos) = self.rotation.sin_cos();
+        let on_plane = |direction: Vec3| direction - normal * direction.dot(normal);
+        let tangent = on_plane(u * cos - v * sin).normalize_or_zero();
+        let bitangent = on_plane(u * sin + v * cos);
+        let sign = if normal.cross(tangent).dot(bitangent) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        tangent.extend(sign)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_world_projection() {
+        let projection = TextureProjection {
+            scale: Vec2::splat(2.0),
+            ..Default::default()
+        };
+        // The uvs of two faces facing the same way line up.
+        let a = projection.uv(Vec3::new(1.0, 0.0, 3.0), Vec3::Y);
+        let b = projection.uv(
+            Vec3::new(1.0, 5.0, 3.0),
+            Vec3::new(0.1, 1.0, 0.0).normalize(),
+        );
+        assert!((a - b).length() < 1e-5);
+        assert!((a - Vec2::new(0.5, 1.5)).length() < 1e-5);
+
+        // u goes right and v down seen from the front of a wall.
+        assert!((projection.uv(Vec3::X, Vec3::Z) - Vec2::new(0.5, 0.0)).length() < 1e-5);
+        assert!((projection.uv(Vec3::Y, Vec3::Z) - Vec2::new(0.0, -0.5)).length() < 1e-5);
+        assert!((projection.uv(Vec3::X, Vec3::NEG_Z) - Vec2::new(-0.5, 0.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn test_tangent() {
+        let normal = Vec3::new(0.0, 1.0, 1.0).normalize();
+        for alignment in [TextureAlignment::World, TextureAlignment::Face] {
+            let projection = TextureProjection {
+                alignment,
+                rotation: 0.4,
+                ..Default::default()
+            };
+            let tangent = projection.tangent(normal);
+            assert!(tangent.truncate().dot(normal).abs() < 1e-5);
+            assert!((tangent.truncate().length() - 1.0).abs() < 1e-5);
+            // Moving along the tangent only increases u.
+            let step = projection.uv(tangent.truncate() * 1e-2, normal)
+                - projection.uv(Vec3::ZERO, normal);
+            assert!(step.x > 0.0);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt;
+
+use super::*;
+
+/// A defect found by [`BrushMesh::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushMeshError {
+    /// The half edge has no twin so the brush is not closed.
+    OpenEdge(HalfEdgeId),
+    /// Another half edge goes between the same vertices in the same direction.
+    /// Either the edge is shared by more than two polygons or the windings are inconsistent.
+    NonManifoldEdge(HalfEdgeId),
+    /// The polygons around the vertice don't form a single fan.
+    NonManifoldVertice(VerticeId),
+    /// The winding of the polygon goes against the normal of its plane.
+    FlippedPolygon(PolygonId),
+    /// The normal of the plane points towards the inside of the brush.
+    InwardPlane(PlaneId),
+    /// A vertice of the polygon doesn't lie on the polygon's plane.
+    PolygonOffPlane {
+        polygon: PolygonId,
+        vertice: VerticeId,
+    },
+    /// The polygon has a reflex vertice.
+    ConcavePolygon(PolygonId),
+    /// A vertice of the brush lies in front of one of its planes.
+    ConcaveBrush { plane: PlaneId, vertice: VerticeId },
+    /// The half edge has a null length.
+    DegenerateEdge(HalfEdgeId),
+    /// The polygon has a null area.
+    DegeneratePolygon(PolygonId),
+    /// The normal of the plane is not a finite unit vector.
+    DegeneratePlane(PlaneId),
+}
+
+impl fmt::Display for BrushMeshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OpenEdge(id) => write!(f, "half edge {id} has no twin, the brush is open"),
+            Self::NonManifoldEdge(id) => write!(f, "half edge {id} is not manifold"),
+            Self::NonManifoldVertice(id) => write!(f, "vertice {id} is not manifold"),
+            Self::FlippedPolygon(id) => {
+                write!(f, "polygon {id} is wound against the normal of its plane")
+            }
+            Self::InwardPlane(id) => write!(f, "plane {id} is facing inward"),
+            Self::PolygonOffPlane { polygon, vertice } => {
+                write!(f, "vertice {vertice} of polygon {polygon} is not on its plane")
+            }
+            Self::ConcavePolygon(id) => write!(f, "polygon {id} is concave"),
+            Self::ConcaveBrush { plane, vertice } => {
+                write!(f, "vertice {vertice} is in front of plane {plane}, the brush is concave")
+            }
+            Self::DegenerateEdge(id) => write!(f, "half edge {id} has a null length"),
+            Self::DegeneratePolygon(id) => write!(f, "polygon {id} has a null area"),
+            Self::DegeneratePlane(id) => write!(f, "plane {id} has an invalid normal"),
+        }
+    }
+}
+
+impl std::error::Error for BrushMeshError {}
+
+impl BrushMesh {
+    /// Check that the brush is a closed convex polyhedron with outward facing polygons.
+    /// All the defects found are returned.
+    pub fn validate(&self) -> Result<(), Vec<BrushMeshError>> {
+        let mut errors = Vec::new();
+        self.validate_planes(&mut errors);
+        self.validate_edges(&mut errors);
+        self.validate_vertices(&mut errors);
+        self.validate_polygons(&mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// A flat brush, like a plane, only has polygons on a single plane so it can't be closed.
+    pub fn is_flat(&self) -> bool {
+        self.planes
+            .iter()
+            .filter(|plane| !plane.polygons.is_empty())
+            .count()
+            <= 1
+    }
+
+    fn validate_planes(&self, errors: &mut Vec<BrushMeshError>) {
+        let center = self.center();
+        for (id, plane) in self.planes.iter().enumerate() {
+            if !plane.normal.is_finite() || !plane.normal.is_normalized() {
+                errors.push(BrushMeshError::DegeneratePlane(id));
+                continue;
+            }
+            // Unused planes have nothing to bound.
+            if plane.polygons.is_empty() {
+                continue;
+            }
+            if plane.distance_to(center) > BRUSH_EPSILON {
+                errors.push(BrushMeshError::InwardPlane(id));
+                continue;
+            }
+            if let Some(vertice) = self
+                .verticies
+                .iter()
+                .position(|vertice| plane.distance_to(vertice.point) > BRUSH_EPSILON)
+            {
+                errors.push(BrushMeshError::ConcaveBrush { plane: id, vertice });
+            }
+        }
+    }
+
+    fn validate_edges(&self, errors: &mut Vec<BrushMeshError>) {
+        for (id, half_edge) in self.half_edges.iter().enumerate() {
+            if half_edge.direction(&self.verticies).length() < BRUSH_EPSILON {
+                errors.push(BrushMeshError::DegenerateEdge(id));
+            }
+            let duplicated = self
+                .get_vertice(half_edge.origin)
+                .out_half_edges
+                .iter()
+                .any(|other| *other != id && self.get_half_edge(*other).end == half_edge.end);
+            if duplicated {
+                errors.push(BrushMeshError::NonManifoldEdge(id));
+            } else if half_edge.twin.is_none() {
+                errors.push(BrushMeshError::OpenEdge(id));
+            }
+        }
+    }
+
+    fn validate_vertices(&self, errors: &mut Vec<BrushMeshError>) {
+        for (id, vertice) in self.verticies.iter().enumerate() {
+            if self.vertice_fan(id).len() != vertice.out_half_edges.len() {
+                errors.push(BrushMeshError::NonManifoldVertice(id));
+            }
+        }
+    }
+
+    fn validate_polygons(&self, errors: &mut Vec<BrushMeshError>) {
+        for (id, polygon) in self.polygons.iter().enumerate() {
+            let winding_normal = polygon.winding_normal(&self.verticies);
+            if winding_normal.length() < BRUSH_EPSILON {
+                errors.push(BrushMeshError::DegeneratePolygon(id));
+                continue;
+            }
+            let plane = self.get_plane(polygon.plane);
+            if winding_normal.dot(plane.normal) < 0.0 {
+                errors.push(BrushMeshError::FlippedPolygon(id));
+            }
+            if let Some(vertice) = polygon.off_plane_vertice(&self.planes, &self.verticies) {
+                errors.push(BrushMeshError::PolygonOffPlane {
+                    polygon: id,
+                    vertice,
+                });
+            }
+            if !polygon.is_convex(&self.half_edges, &self.planes, &self.verticies) {
+                errors.push(BrushMeshError::ConcavePolygon(id));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::csg::Brushable;
+
+    #[test]
+    fn test_valid_brushes() {
+        assert_eq!(
+            Cuboid::from_size(Vec3::ONE).to_default_brush().validate(),
+            Ok(())
+        );
+        let tetrahedron = BrushMesh::convex_hull(&[Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z]).unwrap();
+        assert_eq!(tetrahedron.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_flipped_polygon() {
+        let mut brush = BrushMesh::empty();
+        let verticies = brush.add_vertices([Vec3::ZERO, Vec3::Z, Vec3::X + Vec3::Z, Vec3::X]);
+        let plane = brush.add_plane(Vec3::NEG_Y, Vec3::ZERO);
+        brush.add_polygon_on_plane(&verticies, plane);
+        let errors = brush.validate().unwrap_err();
+        assert!(errors.contains(&BrushMeshError::FlippedPolygon(0)));
+    }
+
+    #[test]
+    fn test_open_brush() {
+        let plane = Plane3d::new(Vec3::Y).to_default_brush();
+        let errors = plane.validate().unwrap_err();
+        assert_eq!(
+            errors,
+            (0..4).map(BrushMeshError::OpenEdge).collect::<Vec<_>>()
+        );
+        assert!(plane.is_flat());
+        assert!(!Cuboid::from_size(Vec3::ONE).to_default_brush().is_flat());
+    }
+
+    #[test]
+    fn test_polygon_off_plane() {
+        let mut brush = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        brush.get_vertice_mut(0).point += Vec3::splat(0.1);
+        let errors = brush.validate().unwrap_err();
+        for polygon in brush.vertice_polygons(0) {
+            assert!(errors.contains(&BrushMeshError::PolygonOffPlane {
+                polygon,
+                vertice: 0
+            }));
+        }
+    }
+
+    #[test]
+    fn test_concave_polygon() {
+        let mut brush = BrushMesh::empty();
+        let verticies = brush.add_vertices([
+            Vec3::ZERO,
+            Vec3::new(1.0, 0.0, 2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 1.0),
+        ]);
+        brush.add_polygon(&verticies);
+        let errors = brush.validate().unwrap_err();
+        assert!(errors.contains(&BrushMeshError::ConcavePolygon(0)));
+    }
+
+    #[test]
+    fn test_degenerate_edge() {
+        let mut brush = BrushMesh::empty();
+        let verticies = brush.add_vertices([Vec3::ZERO, Vec3::Z, Vec3::Z, Vec3::X]);
+        brush.add_polygon(&verticies);
+        let errors = brush.validate().unwrap_err();
+        assert!(errors.contains(&BrushMeshError::DegenerateEdge(1)));
+    }
+}
@@ -1,11 +1,39 @@
-use super::{BrushMesh, CsgLeaf, CsgNode, CsgOp};
+use super::{
+    convert::BrushGroup,
+    evaluate::CsgCache,
+    operations::{BrushMeshOperation, Hollow},
+    BrushMesh, CsgLeaf, CsgNode, CsgOp, InvalidBrush,
+};
 use bevy::ecs::system::EntityCommands;
 use crate::prelude::*;
 
 pub trait CsgCommandsExt {
     fn spawn_csg_node(&mut self, node: SpawnCsgNode) -> EntityCommands<'_>;
+
+    /// Spawn a node whose brushes are generated from `group`.
+    fn spawn_brush_group<G: BrushGroup>(
+        &mut self,
+        parent: Entity,
+        op: CsgOp,
+        group: G,
+    ) -> EntityCommands<'_> {
+        let mut node = self.spawn_csg_node(SpawnCsgNode::node(parent, op).with_name(G::NAME));
+        node.insert(group);
+        node
+    }
+
+    /// Turn a leaf in a node of walls made with `hollow`.
+    fn hollow_brush(&mut self, leaf: Entity, hollow: Hollow);
+
+    /// Turn a node made by [`CsgCommandsExt::hollow_brush`] back in the leaf it was made from.
+    /// The walls are despawned, along with any edit made to them since.
+    fn unhollow_brush(&mut self, node: Entity);
 }
 
+/// Set on the nodes made by hollowing a brush, holds the brush they were made from.
+#[derive(Component, Clone)]
+pub struct Hollowed(pub Handle<BrushMesh>);
+
 impl CsgCommandsExt for Commands<'_, '_> {
     fn spawn_csg_node(&mut self, node: SpawnCsgNode) -> EntityCommands<'_> {
         let mut id = Entity::PLACEHOLDER;
@@ -17,10 +45,64 @@ impl CsgCommandsExt for Commands<'_, '_> {
             } else {
                 spawned.insert(CsgNode);
             }
+            if let Some(name) = node.name {
+                spawned.insert(name);
+            }
             id = spawned.id();
         });
         return self.entity(id);
     }
+
+    fn hollow_brush(&mut self, leaf: Entity, hollow: Hollow) {
+        self.add(move |world: &mut World| {
+            if world.get::<CsgLeaf>(leaf).is_none() {
+                return;
+            }
+            let Some(brush) = world.get::<Handle<BrushMesh>>(leaf).cloned() else {
+                return;
+            };
+            let mut brushes = world.resource_mut::<Assets<BrushMesh>>();
+            let Some(walls) = brushes.get(&brush).map(|brush| hollow.apply(brush)) else {
+                return;
+            };
+            let walls: Vec<Handle<BrushMesh>> =
+                walls.into_iter().map(|wall| brushes.add(wall)).collect();
+            // The entity stays in place so it keeps its order among its siblings.
+            world
+                .entity_mut(leaf)
+                .remove::<(
+                    CsgLeaf,
+                    Handle<BrushMesh>,
+                    Handle<Mesh>,
+                    Handle<StandardMaterial>,
+                    InvalidBrush,
+                )>()
+                .insert((CsgNode, Hollowed(brush)))
+                .with_children(|node| {
+                    for (i, wall) in walls.into_iter().enumerate() {
+                        node.spawn((
+                            Name::new(format!("Wall {}", i + 1)),
+                            CsgLeaf,
+                            CsgOp::Add,
+                            wall,
+                            TransformBundle::default(),
+                        ));
+                    }
+                });
+        });
+    }
+
+    fn unhollow_brush(&mut self, node: Entity) {
+        self.add(move |world: &mut World| {
+            let Some(Hollowed(brush)) = world.get::<Hollowed>(node).cloned() else {
+                return;
+            };
+            let mut node = world.entity_mut(node);
+            node.despawn_descendants()
+                .remove::<(CsgNode, Hollowed, CsgCache)>()
+                .insert((CsgLeaf, brush));
+        });
+    }
 }
 
 pub struct SpawnCsgNode {
@@ -28,4 +110,77 @@ pub struct SpawnCsgNode {
     tranform: Transform,
     op: CsgOp,
     brush: Option<Handle<BrushMesh>>,
+    name: Option<Name>,
+}
+
+impl SpawnCsgNode {
+    /// A group whose children are evaluated first, the result is then combined with its siblings
+    /// using `op`.
+    pub fn node(parent: Entity, op: CsgOp) -> Self {
+        Self {
+            parent,
+            tranform: Transform::IDENTITY,
+            op,
+            brush: None,
+            name: Some(Name::new("Group")),
+        }
+    }
+
+    /// A brush combined with its siblings using `op`.
+    pub fn leaf(parent: Entity, brush: Handle<BrushMesh>, op: CsgOp) -> Self {
+        Self {
+            parent,
+            tranform: Transform::IDENTITY,
+            op,
+            brush: Some(brush),
+            name: None,
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.tranform = transform;
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(Name::new(name.into()));
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::csg::{operations::HollowDirection, Brushable};
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn test_hollow_and_revert() {
+        let mut world = World::new();
+        world.init_resource::<Assets<BrushMesh>>();
+        let brush = world
+            .resource_mut::<Assets<BrushMesh>>()
+            .add(Cuboid::from_size(Vec3::ONE));
+        let leaf = world.spawn((CsgLeaf, CsgOp::Add, brush.clone())).id();
+
+        world.run_system_once(move |mut commands: Commands| {
+            commands.hollow_brush(
+                leaf,
+                Hollow {
+                    thickness: 0.1,
+                    direction: HollowDirection::Inward,
+                },
+            );
+        });
+        assert!(world.get::<CsgNode>(leaf).is_some());
+        assert!(world.get::<CsgLeaf>(leaf).is_none());
+        let walls = world.get::<Children>(leaf).unwrap().to_vec();
+        assert_eq!(walls.len(), 6);
+
+        world.run_system_once(move |mut commands: Commands| commands.unhollow_brush(leaf));
+        assert!(world.get::<CsgLeaf>(leaf).is_some());
+        assert!(world.get::<Hollowed>(leaf).is_none());
+        assert_eq!(world.get::<Handle<BrushMesh>>(leaf), Some(&brush));
+        assert!(walls.iter().all(|wall| world.get_entity(*wall).is_none()));
+    }
 }
@@ -1,7 +1,14 @@
 use crate::prelude::*;
 use bevy::math::primitives::*;
+use bevy::reflect::Struct;
+use std::sync::atomic::{AtomicU32, Ordering};
 
-use super::{BrushMesh, Plane};
+use super::{
+    brush_mesh::{ConvexHullError, PolygonId, Surface, VerticeId, BRUSH_EPSILON},
+    commands::{CsgCommandsExt, SpawnCsgNode},
+    operations::{BakeTransform, BrushMeshOperation},
+    BrushMesh, CsgOp, Plane,
+};
 
 pub trait Brushable: Sized {
     type Settings: Default;
@@ -92,48 +99,1015 @@ impl Brushable for Plane3d {
     }
 }
 
+/// The horizontal direction a wedge shaped brush rises toward.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RisingAxis {
+    X,
+    NegX,
+    Z,
+    #[default]
+    NegZ,
+}
+
+impl RisingAxis {
+    /// The rotation around Y turning -Z into this axis.
+    fn rotation(&self) -> Quat {
+        Quat::from_rotation_y(match self {
+            Self::X => -FRAC_PI_2,
+            Self::NegX => FRAC_PI_2,
+            Self::Z => PI,
+            Self::NegZ => 0.0,
+        })
+    }
+}
+
+/// Where the origin of a brush is.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BrushPivot {
+    /// The center of the bottom face.
+    #[default]
+    Bottom,
+    /// The center of the bounding box.
+    Center,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SlopeSettings {
+    pub pivot: BrushPivot,
+    /// The length of the brush goes along this axis and the width across it.
+    pub rising: RisingAxis,
+}
+
+/// The hull of `points` given with the brush rising toward -Z from the ground, placed according
+/// to `settings`. The brush is empty when one of the `width`, `height` and `length` in `size`
+/// isn't positive, as it would be flat.
+fn wedge_brush(points: Vec<Vec3>, size: Vec3, settings: SlopeSettings) -> BrushMesh {
+    if size.min_element() <= BRUSH_EPSILON {
+        return BrushMesh::empty();
+    }
+    let brush = ConvexHull(points).to_default_brush();
+    let offset = match settings.pivot {
+        BrushPivot::Bottom => Vec3::ZERO,
+        BrushPivot::Center => Vec3::NEG_Y * size.y * 0.5,
+    };
+    let transform = Transform::from_translation(offset).with_rotation(settings.rising.rotation());
+    BakeTransform::from(transform).apply(&brush)
+}
+
+/// The corners of the bottom face and the top corners of a box of the given size going up from
+/// the ground, with the top corners filtered by their sign along x and z.
+fn wedge_corners(
+    width: f32,
+    length: f32,
+    height: f32,
+    top: impl Fn(f32, f32) -> bool,
+) -> Vec<Vec3> {
+    let half = Vec3::new(width * 0.5, 0.0, length * 0.5);
+    let bottom = [half, half.flip_x(), half.flip_xz(), half.flip_z()];
+    let top = bottom
+        .into_iter()
+        .filter(|corner| top(corner.x, corner.z))
+        .map(|corner| corner + Vec3::Y * height);
+    bottom.into_iter().chain(top).collect()
+}
+
+/// A ramp going from the ground up to `height` over its `length`.
 pub struct Slope {
     pub length: f32,
     pub width: f32,
     pub height: f32,
 }
 
-impl From<Slope> for BrushMesh {
-    fn from(val: Slope) -> Self {
-        let mut brush = BrushMesh::empty();
-        let half = Vec3::new(val.width * 0.5, 0.0, val.length * 0.5);
-
-        brush.add_vertices([
-            half,
-            half.flip_x(),
-            half.flip_xz(),
-            half.flip_z(),
-            half.flip_xz() + Vec3::Y * val.height,
-            half.flip_z() + Vec3::Y * val.height,
-        ]);
-        let bottom = brush.add_plane(Vec3::NEG_Y, Vec3::ZERO);
-        let slope = brush.add_plane(
-            Vec3::new(0.0, val.length, val.height).normalize(),
-            Vec3::new(0.0, 0.5 * val.height, 0.0),
-        );
-        let back = brush.add_plane(
-            Vec3::NEG_Z,
-            Vec3::new(0.0, 0.5 * val.height, -val.length * 0.5),
-        );
-        let side = brush.add_plane(
-                    Vec3::X,
-            Vec3::X * val.width * 0.5,
-        );
-        let side_neg = brush.add_plane(
-                    Vec3::NEG_X,
-            Vec3::NEG_X * val.width * 0.5,
-        );
-
-        brush.add_polygon_on_plane(&[0, 1, 2, 3], bottom);
-        brush.add_polygon_on_plane(&[0, 5, 4, 1], slope);
-        brush.add_polygon_on_plane(&[4, 5, 3, 2], back);
-        brush.add_polygon_on_plane(&[0, 3, 5], side);
-        brush.add_polygon_on_plane(&[1, 4, 2], side_neg);
+impl Brushable for Slope {
+    type Settings = SlopeSettings;
+
+    fn to_brush(self, settings: Self::Settings) -> BrushMesh {
+        let corners = wedge_corners(self.width, self.length, self.height, |_, z| z < 0.0);
+        let size = Vec3::new(self.width, self.height, self.length);
+        wedge_brush(corners, size, settings)
+    }
+}
+
+/// The outer corner where two slopes meet, a pyramid with its top above a corner of its base.
+pub struct CornerWedge {
+    pub length: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Brushable for CornerWedge {
+    type Settings = SlopeSettings;
+
+    /// The top is above the -X corner on the rising side.
+    fn to_brush(self, settings: Self::Settings) -> BrushMesh {
+        let corners = wedge_corners(self.width, self.length, self.height, |x, z| {
+            x < 0.0 && z < 0.0
+        });
+        let size = Vec3::new(self.width, self.height, self.length);
+        wedge_brush(corners, size, settings)
+    }
+}
+
+/// The inner corner where two slopes meet, a box with a top corner cut off.
+pub struct InvertedCorner {
+    pub length: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Brushable for InvertedCorner {
+    type Settings = SlopeSettings;
+
+    /// The cut corner is the +X one on the low side.
+    fn to_brush(self, settings: Self::Settings) -> BrushMesh {
+        let corners = wedge_corners(self.width, self.length, self.height, |x, z| {
+            x < 0.0 || z < 0.0
+        });
+        let size = Vec3::new(self.width, self.height, self.length);
+        wedge_brush(corners, size, settings)
+    }
+}
+
+/// A pyramid with a rectangular base and its top above the center.
+pub struct Spike {
+    pub length: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Brushable for Spike {
+    type Settings = SlopeSettings;
+
+    fn to_brush(self, settings: Self::Settings) -> BrushMesh {
+        let mut corners = wedge_corners(self.width, self.length, self.height, |_, _| false);
+        corners.push(Vec3::Y * self.height);
+        let size = Vec3::new(self.width, self.height, self.length);
+        wedge_brush(corners, size, settings)
+    }
+}
+
+/// A parametric group of convex brushes, set on a [`CsgNode`](super::CsgNode) whose children are
+/// regenerated each time the component changes.
+/// Its fields are edited through reflection so they must be numbers or optional numbers.
+pub trait BrushGroup: Component + Struct + Default {
+    /// The name of the node.
+    const NAME: &'static str;
+    /// The name of the generated leaves, followed by their index.
+    const PART: &'static str;
+    /// The fields that can go below zero, the other ones are sizes.
+    const SIGNED_FIELDS: &'static [&'static str] = &[];
+
+    /// The brushes of the group with their transform relative to the node.
+    fn brushes(&self) -> Vec<(Transform, BrushMesh)>;
+}
+
+/// Replace the children of the changed groups with their newly generated brushes.
+pub fn regenerate_brush_groups<G: BrushGroup>(
+    mut commands: Commands,
+    mut brushes: ResMut<Assets<BrushMesh>>,
+    group_query: Query<(Entity, &G, Option<&Children>), Changed<G>>,
+) {
+    for (entity, group, children) in &group_query {
+        for child in children.into_iter().flatten() {
+            commands.entity(*child).despawn_recursive();
+        }
+        for (i, (transform, brush)) in group.brushes().into_iter().enumerate() {
+            commands.spawn_csg_node(
+                SpawnCsgNode::leaf(entity, brushes.add(brush), CsgOp::Add)
+                    .with_transform(transform)
+                    .with_name(format!("{} {}", G::PART, i + 1)),
+            );
+        }
+    }
+}
+
+/// A staircase climbing along +Z from the origin, each step is a column of its own going down to
+/// the ground.
+#[derive(Component, Reflect, Clone, PartialEq, Debug)]
+#[reflect(Component)]
+pub struct Stairs {
+    pub steps: usize,
+    pub step_height: f32,
+    pub step_depth: f32,
+    pub width: f32,
+    /// The depth of the flat floor at the top of the stairs.
+    pub landing: Option<f32>,
+}
+
+impl Default for Stairs {
+    fn default() -> Self {
+        Self {
+            steps: 8,
+            step_height: 0.25,
+            step_depth: 0.3,
+            width: 1.0,
+            landing: None,
+        }
+    }
+}
+
+impl BrushGroup for Stairs {
+    const NAME: &'static str = "Stairs";
+    const PART: &'static str = "Step";
+
+    fn brushes(&self) -> Vec<(Transform, BrushMesh)> {
+        let column = |height: f32, start: f32, depth: f32| {
+            let size = Vec3::new(self.width, height, depth);
+            (
+                Transform::from_translation(Vec3::new(0.0, height, 2.0 * start + depth) * 0.5),
+                Cuboid::from_size(size).to_default_brush(),
+            )
+        };
+        let mut brushes: Vec<_> = (0..self.steps)
+            .map(|step| {
+                column(
+                    self.step_height * (step + 1) as f32,
+                    self.step_depth * step as f32,
+                    self.step_depth,
+                )
+            })
+            .collect();
+        // A landing without steps would have no height.
+        let landing = self
+            .landing
+            .filter(|landing| *landing > 0.0 && self.steps > 0);
+        if let Some(landing) = landing {
+            brushes.push(column(
+                self.step_height * self.steps as f32,
+                self.step_depth * self.steps as f32,
+                landing,
+            ));
+        }
+        brushes
+    }
+}
+
+/// The convex pieces of a ring between the `inner` and `outer` radiuses around the Z axis, from
+/// `start` to `end` angles counter clockwise from +X, extruded over `depth`.
+/// The pieces without volume are skipped.
+fn ring_segments(
+    inner: f32,
+    outer: f32,
+    (start, end): (f32, f32),
+    segments: usize,
+    depth: f32,
+) -> Vec<(Transform, BrushMesh)> {
+    let span = end - start;
+    // A piece spanning more than a half turn wouldn't be convex.
+    let segments = segments
+        .max((span.abs() / FRAC_PI_2).ceil() as usize)
+        .max(1);
+    let at = |radius: f32, segment: usize| {
+        let angle = start + span * segment as f32 / segments as f32;
+        Vec2::from_angle(angle) * radius
+    };
+    let group = new_smoothing_group();
+    (0..segments)
+        .filter_map(|segment| {
+            let profile = [
+                at(inner, segment),
+                at(outer, segment),
+                at(outer, segment + 1),
+                at(inner, segment + 1),
+            ];
+            let mut brush = extruded_profile(&profile, depth)?;
+            // The inner and outer faces are curved.
+            smooth_extruded_side(&mut brush, profile[1], profile[2], group);
+            smooth_extruded_side(&mut brush, profile[3], profile[0], group);
+            Some((Transform::IDENTITY, brush))
+        })
+        .collect()
+}
+
+/// The convex brush of a profile in the XY plane extruded over `depth` centered on Z, if it has
+/// a volume.
+fn extruded_profile(profile: &[Vec2], depth: f32) -> Option<BrushMesh> {
+    let points = profile
+        .iter()
+        .flat_map(|point| [-0.5, 0.5].map(|side| point.extend(side * depth)))
+        .collect();
+    ConvexHull(points).try_to_brush().ok()
+}
+
+/// Put the face extruded from the edge of a profile going from `start` to `end` in `group`.
+fn smooth_extruded_side(brush: &mut BrushMesh, start: Vec2, end: Vec2, group: u32) {
+    let Some(direction) = (end - start).try_normalize() else {
+        return;
+    };
+    let sides: Vec<PolygonId> = brush
+        .polygons()
+        .filter(|poly| {
+            poly.verticies().all(|vertice| {
+                direction.perp_dot(vertice.point.truncate() - start).abs() < BRUSH_EPSILON
+            })
+        })
+        .map(|poly| poly.id)
+        .collect();
+    for side in sides {
+        smooth_polygon(brush, side, group);
+    }
+}
+
+/// A circular arch standing in the XY plane, extruded along Z.
+#[derive(Component, Reflect, Clone, PartialEq, Debug)]
+#[reflect(Component)]
+pub struct Arch {
+    /// The radius of the inside of the arch.
+    pub radius: f32,
+    pub thickness: f32,
+    pub depth: f32,
+    /// The angles the arch goes through, counter clockwise from +X.
+    pub start_angle: f32,
+    pub end_angle: f32,
+    pub segments: usize,
+}
+
+impl Default for Arch {
+    fn default() -> Self {
+        Self {
+            radius: 2.0,
+            thickness: 0.5,
+            depth: 1.0,
+            start_angle: 0.0,
+            end_angle: PI,
+            segments: 8,
+        }
+    }
+}
+
+impl BrushGroup for Arch {
+    const NAME: &'static str = "Arch";
+    const PART: &'static str = "Segment";
+    const SIGNED_FIELDS: &'static [&'static str] = &["start_angle", "end_angle"];
+
+    fn brushes(&self) -> Vec<(Transform, BrushMesh)> {
+        ring_segments(
+            self.radius,
+            self.radius + self.thickness,
+            (self.start_angle, self.end_angle),
+            self.segments,
+            self.depth,
+        )
+    }
+}
+
+/// A hollow tube along Z.
+#[derive(Component, Reflect, Clone, PartialEq, Debug)]
+#[reflect(Component)]
+pub struct Pipe {
+    /// The radius of the inside of the pipe.
+    pub radius: f32,
+    pub thickness: f32,
+    pub length: f32,
+    pub segments: usize,
+}
+
+impl Default for Pipe {
+    fn default() -> Self {
+        Self {
+            radius: 1.0,
+            thickness: 0.25,
+            length: 4.0,
+            segments: 16,
+        }
+    }
+}
+
+impl BrushGroup for Pipe {
+    const NAME: &'static str = "Pipe";
+    const PART: &'static str = "Segment";
+
+    fn brushes(&self) -> Vec<(Transform, BrushMesh)> {
+        ring_segments(
+            self.radius,
+            self.radius + self.thickness,
+            (0.0, TAU),
+            self.segments,
+            self.length,
+        )
+    }
+}
+
+/// A ramp curving up from the ground at the origin to a wall at `radius` along +X, extruded
+/// along Z.
+#[derive(Component, Reflect, Clone, PartialEq, Debug)]
+#[reflect(Component)]
+pub struct QuarterPipe {
+    pub radius: f32,
+    pub width: f32,
+    /// The angles of the curve the ramp covers, from 0 on the ground to a quarter turn on the wall.
+    pub start_angle: f32,
+    pub end_angle: f32,
+    pub segments: usize,
+}
+
+impl Default for QuarterPipe {
+    fn default() -> Self {
+        Self {
+            radius: 2.0,
+            width: 2.0,
+            start_angle: 0.0,
+            end_angle: FRAC_PI_2,
+            segments: 8,
+        }
+    }
+}
+
+impl BrushGroup for QuarterPipe {
+    const NAME: &'static str = "Quarter Pipe";
+    const PART: &'static str = "Segment";
+    const SIGNED_FIELDS: &'static [&'static str] = &["start_angle", "end_angle"];
+
+    fn brushes(&self) -> Vec<(Transform, BrushMesh)> {
+        let segments = self.segments.max(1);
+        let span = self.end_angle - self.start_angle;
+        // The curve is a circle centered above the origin.
+        let curve = |segment: usize| {
+            let angle = self.start_angle + span * segment as f32 / segments as f32;
+            Vec2::new(angle.sin(), 1.0 - angle.cos()) * self.radius
+        };
+        let group = new_smoothing_group();
+        (0..segments)
+            .filter_map(|segment| {
+                let (a, b) = (curve(segment), curve(segment + 1));
+                let profile = [a, Vec2::new(a.x, 0.0), Vec2::new(b.x, 0.0), b];
+                let mut brush = extruded_profile(&profile, self.width)?;
+                smooth_extruded_side(&mut brush, b, a, group);
+                Some((Transform::IDENTITY, brush))
+            })
+            .collect()
+    }
+}
+
+/// Where the first segment of a round brush starts around its axis.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SegmentAlignment {
+    /// A vertice is on the +X axis.
+    #[default]
+    Vertice,
+    /// A face is centered on the +X axis.
+    Face,
+}
+
+pub struct RoundBrushSettings {
+    /// The number of faces around the axis, at least 3.
+    pub segments: usize,
+    pub alignment: SegmentAlignment,
+}
+
+impl Default for RoundBrushSettings {
+    fn default() -> Self {
+        Self {
+            segments: 16,
+            alignment: SegmentAlignment::Vertice,
+        }
+    }
+}
+
+impl RoundBrushSettings {
+    /// The points of a circle of `radius` around the Y axis at `height`, in increasing angle.
+    fn ring(&self, radius: f32, height: f32) -> Vec<Vec3> {
+        let segments = self.segments.max(3);
+        let step = TAU / segments as f32;
+        let offset = match self.alignment {
+            SegmentAlignment::Vertice => 0.0,
+            SegmentAlignment::Face => step * 0.5,
+        };
+        (0..segments)
+            .map(|i| {
+                let angle = offset + step * i as f32;
+                Vec3::new(radius * angle.cos(), height, radius * angle.sin())
+            })
+            .collect()
+    }
+}
+
+/// A smoothing group no other generated brush has, so the curved faces of two round brushes
+/// touching each other aren't smoothed together.
+pub fn new_smoothing_group() -> u32 {
+    static NEXT_GROUP: AtomicU32 = AtomicU32::new(1);
+    NEXT_GROUP.fetch_add(1, Ordering::Relaxed)
+}
+
+fn smooth_polygon(brush: &mut BrushMesh, polygon: PolygonId, group: u32) {
+    let surface = brush.get_polygon(polygon).surface;
+    brush.set_polygon_surface(
+        polygon,
+        Surface {
+            smoothing_group: Some(group),
+            ..surface
+        },
+    );
+}
+
+/// A brush around the Y axis going from a circle of `bottom` radius to one of `top` radius.
+/// A null radius closes the brush with a tip.
+fn frustum_brush(
+    bottom: f32,
+    top: f32,
+    half_height: f32,
+    settings: RoundBrushSettings,
+) -> BrushMesh {
+    let mut brush = BrushMesh::empty();
+    let end = |brush: &mut BrushMesh, radius: f32, height: f32| {
+        if radius > BRUSH_EPSILON {
+            brush.extend_verticies(settings.ring(radius, height))
+        } else {
+            vec![brush.add_vertice(Vec3::Y * height)]
+        }
+    };
+    let bottom = end(&mut brush, bottom, -half_height);
+    let top = end(&mut brush, top, half_height);
+    if bottom.len() == 1 && top.len() == 1 {
+        return BrushMesh::empty();
+    }
+
+    // Increasing angles go clockwise seen from above.
+    if bottom.len() > 1 {
+        let plane = brush.add_plane(Vec3::NEG_Y, Vec3::NEG_Y * half_height);
+        brush.add_polygon_on_plane(&bottom, plane);
+    }
+    if top.len() > 1 {
+        let plane = brush.add_plane(Vec3::Y, Vec3::Y * half_height);
+        let reversed: Vec<VerticeId> = top.iter().rev().copied().collect();
+        brush.add_polygon_on_plane(&reversed, plane);
+    }
+    let segments = bottom.len().max(top.len());
+    let group = new_smoothing_group();
+    let at = |ring: &[VerticeId], i: usize| ring[i % ring.len()];
+    for i in 0..segments {
+        let mut side = vec![
+            at(&bottom, i + 1),
+            at(&bottom, i),
+            at(&top, i),
+            at(&top, i + 1),
+        ];
+        side.dedup();
+        let side = brush.add_polygon(&side);
+        smooth_polygon(&mut brush, side, group);
+    }
+    brush
+}
+
+impl Brushable for Cylinder {
+    type Settings = RoundBrushSettings;
+
+    fn to_brush(self, settings: Self::Settings) -> BrushMesh {
+        frustum_brush(self.radius, self.radius, self.half_height, settings)
+    }
+}
+
+impl Brushable for Cone {
+    type Settings = RoundBrushSettings;
+
+    /// The base is at the bottom and the tip at the top.
+    fn to_brush(self, settings: Self::Settings) -> BrushMesh {
+        frustum_brush(self.radius, 0.0, self.height * 0.5, settings)
+    }
+}
+
+impl Brushable for ConicalFrustum {
+    type Settings = RoundBrushSettings;
+
+    fn to_brush(self, settings: Self::Settings) -> BrushMesh {
+        frustum_brush(
+            self.radius_bottom,
+            self.radius_top,
+            self.height * 0.5,
+            settings,
+        )
+    }
+}
+
+/// How the surface of a sphere is split in faces.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SphereBrushSettings {
+    /// Rings of quads between the poles, like the lines of a globe.
+    Uv { sectors: usize, stacks: usize },
+    /// An icosahedron with each triangle split in four `subdivisions` times, at most 4.
+    Ico { subdivisions: usize },
+}
+
+impl Default for SphereBrushSettings {
+    fn default() -> Self {
+        Self::Uv {
+            sectors: 16,
+            stacks: 8,
+        }
+    }
+}
+
+/// The point of a triangle split `weights.sum()` times on each side, a power of two, placed like
+/// the recursive splitting of each edge at its middle pushed on the unit sphere.
+fn subdivided(triangle: &[Vec3; 3], weights: [usize; 3]) -> Vec3 {
+    let total: usize = weights.iter().sum();
+    if let Some(corner) = weights.iter().position(|weight| *weight == total) {
+        return triangle[corner];
+    }
+    let odd: Vec<usize> = (0..3).filter(|i| weights[*i] % 2 == 1).collect();
+    let [a, b] = odd[..] else {
+        return subdivided(triangle, weights.map(|weight| weight / 2));
+    };
+    // The point is the middle of an edge of the triangles split half as many times.
+    let (mut lower, mut upper) = (weights, weights);
+    (lower[a], lower[b]) = (weights[a] - 1, weights[b] + 1);
+    (upper[a], upper[b]) = (weights[a] + 1, weights[b] - 1);
+    let (lower, upper) = (
+        lower.map(|weight| weight / 2),
+        upper.map(|weight| weight / 2),
+    );
+    (subdivided(triangle, lower) + subdivided(triangle, upper)).normalize()
+}
+
+impl SphereBrushSettings {
+    /// The vertices of a sphere of `radius` centered on the origin.
+    fn points(&self, radius: f32) -> Vec<Vec3> {
+        match *self {
+            Self::Uv { sectors, stacks } => {
+                let stacks = stacks.max(2);
+                let ring = RoundBrushSettings {
+                    segments: sectors,
+                    alignment: SegmentAlignment::Vertice,
+                };
+                let mut points = vec![Vec3::Y * radius, Vec3::NEG_Y * radius];
+                for stack in 1..stacks {
+                    let angle = PI * stack as f32 / stacks as f32;
+                    points.extend(ring.ring(radius * angle.sin(), radius * angle.cos()));
+                }
+                points
+            }
+            Self::Ico { subdivisions } => {
+                let phi = (1.0 + 5f32.sqrt()) * 0.5;
+                let corners: Vec<Vec3> = [
+                    (-1.0, phi, 0.0),
+                    (1.0, phi, 0.0),
+                    (-1.0, -phi, 0.0),
+                    (1.0, -phi, 0.0),
+                    (0.0, -1.0, phi),
+                    (0.0, 1.0, phi),
+                    (0.0, -1.0, -phi),
+                    (0.0, 1.0, -phi),
+                    (phi, 0.0, -1.0),
+                    (phi, 0.0, 1.0),
+                    (-phi, 0.0, -1.0),
+                    (-phi, 0.0, 1.0),
+                ]
+                .into_iter()
+                .map(|(x, y, z)| Vec3::new(x, y, z).normalize())
+                .collect();
+                #[rustfmt::skip]
+                let triangles = [
+                    [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+                    [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+                    [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+                    [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+                ];
+                // The points of each triangle split in `n * n` triangles, the faces of the hull are
+                // rebuilt from the points only.
+                let n = 1 << subdivisions.min(4);
+                let mut points: Vec<Vec3> = corners.iter().map(|corner| *corner * radius).collect();
+                for [a, b, c] in triangles {
+                    let triangle = [corners[a], corners[b], corners[c]];
+                    for i in 1..n {
+                        for j in 1..n - i {
+                            points.push(subdivided(&triangle, [n - i - j, i, j]) * radius);
+                        }
+                    }
+                    // An edge is shared by two triangles, only one of them adds its points.
+                    for (start, end) in [(0, 1), (1, 2), (2, 0)] {
+                        if [a, b, c][start] < [a, b, c][end] {
+                            points.extend((1..n).map(|i| {
+                                let mut weights = [0; 3];
+                                (weights[start], weights[end]) = (n - i, i);
+                                subdivided(&triangle, weights) * radius
+                            }));
+                        }
+                    }
+                }
+                points
+            }
+        }
+    }
+}
+
+impl Brushable for Sphere {
+    type Settings = SphereBrushSettings;
+
+    fn to_brush(self, settings: Self::Settings) -> BrushMesh {
+        let mut brush = ConvexHull(settings.points(self.radius)).to_default_brush();
+        let group = new_smoothing_group();
+        for polygon in 0..brush.polygons().count() {
+            smooth_polygon(&mut brush, polygon, group);
+        }
+        brush
+    }
+}
+
+impl Brushable for Capsule3d {
+    type Settings = SphereBrushSettings;
+
+    /// The hemispheres are the halves of the sphere, moved apart along the Y axis.
+    fn to_brush(self, settings: Self::Settings) -> BrushMesh {
+        let mut points = Vec::new();
+        for point in settings.points(self.radius) {
+            if point.y >= -BRUSH_EPSILON {
+                points.push(point + Vec3::Y * self.half_length);
+            }
+            if point.y <= BRUSH_EPSILON {
+                points.push(point - Vec3::Y * self.half_length);
+            }
+        }
+        let mut brush = ConvexHull(points).to_default_brush();
+        let group = new_smoothing_group();
+        for polygon in 0..brush.polygons().count() {
+            smooth_polygon(&mut brush, polygon, group);
+        }
         brush
     }
 }
+
+/// The smallest convex brush containing a set of points.
+pub struct ConvexHull(pub Vec<Vec3>);
+
+impl ConvexHull {
+    pub fn try_to_brush(self) -> Result<BrushMesh, ConvexHullError> {
+        BrushMesh::convex_hull(&self.0)
+    }
+}
+
+impl Brushable for ConvexHull {
+    type Settings = ();
+
+    /// Degenerate point sets give an empty brush, use [`ConvexHull::try_to_brush`] to get the error.
+    fn to_brush(self, _settings: Self::Settings) -> BrushMesh {
+        self.try_to_brush().unwrap_or_else(|error| {
+            warn!("Couldn't build the convex hull: {error}.");
+            BrushMesh::empty()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bevy::math::bounding::Bounded3d;
+
+    #[test]
+    fn test_round_brushes() {
+        let settings = || RoundBrushSettings {
+            segments: 32,
+            alignment: SegmentAlignment::Face,
+        };
+        let cylinder = Cylinder::new(1.0, 2.0);
+        let cone = Cone {
+            radius: 1.0,
+            height: 2.0,
+        };
+        let frustum = ConicalFrustum {
+            radius_top: 0.5,
+            radius_bottom: 1.0,
+            height: 2.0,
+        };
+        for (brush, volume) in [
+            (cylinder.to_brush(settings()), cylinder.volume()),
+            (cone.to_brush(settings()), cone.volume()),
+            (
+                frustum.to_brush(settings()),
+                PI * 2.0 * (1.0 + 0.5 + 0.25) / 3.0,
+            ),
+        ] {
+            assert_eq!(brush.validate(), Ok(()));
+            // The brushes are inscribed in the round shapes.
+            assert!(brush.volume() < volume && brush.volume() > volume * 0.95);
+        }
+
+        // A pillar standing on a ball isn't smoothed with it.
+        let group = |brush: BrushMesh| {
+            brush
+                .polygons()
+                .find_map(|poly| poly.surface.smoothing_group)
+        };
+        let pillar = group(cylinder.to_brush(settings()));
+        assert!(pillar.is_some());
+        assert_ne!(pillar, group(Sphere::new(1.0).to_default_brush()));
+        assert_ne!(pillar, group(cylinder.to_brush(settings())));
+    }
+
+    #[test]
+    fn test_sphere_brushes() {
+        let uv = SphereBrushSettings::Uv {
+            sectors: 16,
+            stacks: 8,
+        };
+        let ico = SphereBrushSettings::Ico { subdivisions: 2 };
+        let sphere = Sphere::new(1.0);
+        let capsule = Capsule3d::new(1.0, 2.0);
+
+        let brush = sphere.to_brush(uv);
+        assert_eq!(brush.validate(), Ok(()));
+        // The quads between the rings are planar, the triangles at the poles too.
+        assert_eq!(brush.polygons().count(), 16 * 8);
+        assert_eq!(brush.planes().count(), brush.polygons().count());
+
+        for subdivisions in 0..=3 {
+            let brush = sphere.to_brush(SphereBrushSettings::Ico { subdivisions });
+            assert_eq!(brush.validate(), Ok(()));
+            assert_eq!(brush.polygons().count(), 20 << (2 * subdivisions));
+            assert_eq!(brush.vertices().count(), 10 * (1 << (2 * subdivisions)) + 2);
+        }
+        let brush = sphere.to_brush(ico);
+        assert!(brush.volume() < sphere.volume() && brush.volume() > sphere.volume() * 0.9);
+
+        for settings in [uv, ico] {
+            let brush = capsule.to_brush(settings);
+            assert_eq!(brush.validate(), Ok(()));
+            assert!(brush.volume() < capsule.volume() && brush.volume() > capsule.volume() * 0.9);
+        }
+    }
+
+    #[test]
+    fn test_stairs() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let mut world = World::new();
+        world.init_resource::<Assets<BrushMesh>>();
+        let stairs = Stairs {
+            steps: 4,
+            step_height: 0.5,
+            step_depth: 1.0,
+            width: 2.0,
+            landing: Some(2.0),
+        };
+        let volume: f32 = stairs
+            .brushes()
+            .iter()
+            .map(|(_, brush)| brush.volume())
+            .sum();
+        assert!((volume - 2.0 * (0.5 + 1.0 + 1.5 + 2.0 + 2.0 * 2.0)).abs() < 1e-4);
+
+        let node = world.spawn(stairs).id();
+        world.run_system_once(regenerate_brush_groups::<Stairs>);
+        assert_eq!(world.get::<Children>(node).unwrap().len(), 5);
+
+        world.get_mut::<Stairs>(node).unwrap().landing = None;
+        world.run_system_once(regenerate_brush_groups::<Stairs>);
+        let children = world.get::<Children>(node).unwrap();
+        assert_eq!(children.len(), 4);
+        let top = world.get::<Transform>(children[3]).unwrap();
+        assert_eq!(top.translation, Vec3::new(0.0, 1.0, 3.5));
+
+        let flat = Stairs {
+            steps: 0,
+            landing: Some(1.0),
+            ..Default::default()
+        };
+        assert!(flat.brushes().is_empty());
+    }
+
+    #[test]
+    fn test_ring_groups() {
+        let brushes = Pipe::default().brushes();
+        assert_eq!(brushes.len(), 16);
+        let arch = Arch {
+            segments: 1,
+            ..Default::default()
+        };
+        // A half turn is split so every piece stays convex.
+        assert_eq!(arch.brushes().len(), 2);
+        let ramp = QuarterPipe::default();
+        let volume: f32 = ramp.brushes().iter().map(|(_, brush)| brush.volume()).sum();
+        // The square under the curve minus the quarter circle, a bit more with the chords.
+        let expected = ramp.width * ramp.radius * ramp.radius * (1.0 - FRAC_PI_4);
+        assert!(volume > expected && volume < expected * 1.1);
+        for (_, brush) in brushes.iter().chain(&arch.brushes()).chain(&ramp.brushes()) {
+            assert_eq!(brush.validate(), Ok(()));
+        }
+        // The pieces without volume aren't generated.
+        let flat = Pipe {
+            thickness: 0.0,
+            ..Default::default()
+        };
+        assert!(flat.brushes().is_empty());
+        let closed = Arch {
+            start_angle: 1.0,
+            end_angle: 1.0,
+            ..Default::default()
+        };
+        assert!(closed.brushes().is_empty());
+        let thin = QuarterPipe {
+            width: 0.0,
+            ..Default::default()
+        };
+        assert!(thin.brushes().is_empty());
+
+        // The curved faces of a group are smoothed together, apart from the other groups.
+        let groups = |brushes: &[(Transform, BrushMesh)]| -> Vec<Vec<Option<u32>>> {
+            brushes
+                .iter()
+                .map(|(_, brush)| {
+                    brush
+                        .polygons()
+                        .map(|poly| poly.surface.smoothing_group)
+                        .collect()
+                })
+                .collect()
+        };
+        let pipe = groups(&brushes);
+        let group = pipe[0].iter().flatten().next().copied();
+        assert!(group.is_some());
+        for segment in &pipe {
+            // The inner and outer faces, not the ends nor the caps.
+            assert_eq!(segment.iter().filter(|other| **other == group).count(), 2);
+            assert_eq!(segment.iter().filter(|other| other.is_none()).count(), 4);
+        }
+        for segment in groups(&ramp.brushes()) {
+            assert_eq!(segment.iter().flatten().count(), 1);
+            assert!(!segment.contains(&group));
+        }
+    }
+
+    #[test]
+    fn test_wedges() {
+        let (width, length, height) = (1.0, 2.0, 3.0);
+        let size = width * length * height;
+        let axes = [
+            RisingAxis::X,
+            RisingAxis::NegX,
+            RisingAxis::Z,
+            RisingAxis::NegZ,
+        ];
+        for rising in axes {
+            for pivot in [BrushPivot::Bottom, BrushPivot::Center] {
+                let settings = SlopeSettings { pivot, rising };
+                for (brush, volume) in [
+                    (
+                        Slope {
+                            length,
+                            width,
+                            height,
+                        }
+                        .to_brush(settings),
+                        size / 2.0,
+                    ),
+                    (
+                        CornerWedge {
+                            length,
+                            width,
+                            height,
+                        }
+                        .to_brush(settings),
+                        size / 3.0,
+                    ),
+                    (
+                        InvertedCorner {
+                            length,
+                            width,
+                            height,
+                        }
+                        .to_brush(settings),
+                        size * 5.0 / 6.0,
+                    ),
+                    (
+                        Spike {
+                            length,
+                            width,
+                            height,
+                        }
+                        .to_brush(settings),
+                        size / 3.0,
+                    ),
+                ] {
+                    assert_eq!(brush.validate(), Ok(()));
+                    assert!((brush.volume() - volume).abs() < 1e-4);
+                }
+            }
+        }
+
+        let slope = Slope {
+            length,
+            width,
+            height,
+        }
+        .to_brush(SlopeSettings {
+            pivot: BrushPivot::Center,
+            rising: RisingAxis::X,
+        });
+        // The slope faces the low side, up and toward -X.
+        let slope_plane = slope
+            .planes()
+            .find(|plane| plane.normal.x < -BRUSH_EPSILON && plane.normal.y > BRUSH_EPSILON)
+            .unwrap();
+        assert!((slope_plane.normal - Vec3::new(-height, length, 0.0).normalize()).length() < 1e-4);
+        let aabb = slope.aabb_3d(Vec3::ZERO, Quat::IDENTITY);
+        assert!((aabb.min - Vec3::new(-1.0, -1.5, -0.5)).length() < 1e-4);
+
+        // A flat wedge isn't sent to the convex hull.
+        let flat = Spike {
+            length,
+            width,
+            height: 0.0,
+        }
+        .to_default_brush();
+        assert_eq!(flat.polygons().count(), 0);
+    }
+}
@@ -0,0 +1,860 @@
+use bevy::render::primitives::Aabb;
+use bevy::utils::{HashMap, HashSet};
+
+use crate::common::physics::prelude::{Collider, CollisionGroups, RigidBody};
+use crate::prelude::*;
+
+use super::{
+    broadphase::HierarchicalHashGrid,
+    brush_mesh::{BrushMaterial, BrushMesh},
+    materials::BrushMaterials,
+    operations::{
+        compound_collider, environement_groups, intersect, merge_brushes_with, subtract,
+        BakeTransform, BrushMeshOperation, Cleanup, Smoothing, TriangulateMaterials,
+        TrimeshCollider,
+    },
+    CsgCollider, CsgLeaf, CsgLightmap, CsgNode, CsgOp, CsgRoot, Dirty,
+};
+
+/// A convex piece of an evaluated csg node with the leaf it was cut from.
+#[derive(Clone)]
+pub struct CsgPiece {
+    pub leaf: Entity,
+    pub brush: BrushMesh,
+}
+
+impl AsRef<BrushMesh> for CsgPiece {
+    fn as_ref(&self) -> &BrushMesh {
+        &self.brush
+    }
+}
+
+/// Tells if two pieces may overlap from the leaves they were cut from.
+/// A piece is always inside of its leaf so two pieces can only overlap if their leaves do.
+pub struct Overlaps<'a> {
+    broadphase: &'a HierarchicalHashGrid,
+    leaves: HashMap<Entity, HashSet<Entity>>,
+}
+
+impl<'a> Overlaps<'a> {
+    pub fn new(broadphase: &'a HierarchicalHashGrid) -> Self {
+        Self {
+            broadphase,
+            leaves: HashMap::new(),
+        }
+    }
+
+    pub fn test(&mut self, a: &CsgPiece, b: &CsgPiece) -> bool {
+        // Leaves missing from the broadphase can overlap anything.
+        if a.leaf == b.leaf
+            || !self.broadphase.contains(a.leaf)
+            || !self.broadphase.contains(b.leaf)
+        {
+            return true;
+        }
+        let broadphase = self.broadphase;
+        self.leaves
+            .entry(a.leaf)
+            .or_insert_with(|| broadphase.overlapping(a.leaf).into_iter().collect())
+            .contains(&b.leaf)
+    }
+}
+
+/// Combine a set of convex pieces with another set according to `op`.
+/// Both sets must be in the same space, only the pieces that may overlap are intersected.
+pub fn combine(
+    set: Vec<CsgPiece>,
+    other: &[CsgPiece],
+    op: CsgOp,
+    overlaps: &mut Overlaps,
+) -> Vec<CsgPiece> {
+    match op {
+        CsgOp::Add => {
+            let mut result = set;
+            for piece in other {
+                let mut brushes = vec![piece.brush.clone()];
+                for existing in &result {
+                    if !overlaps.test(piece, existing) {
+                        continue;
+                    }
+                    brushes = brushes
+                        .iter()
+                        .flat_map(|brush| subtract(brush, &existing.brush))
+                        .collect();
+                }
+                result.extend(brushes.into_iter().map(|brush| CsgPiece {
+                    leaf: piece.leaf,
+                    brush,
+                }));
+            }
+            result
+        }
+        CsgOp::Substract => other.iter().fold(set, |set, carved| {
+            set.into_iter()
+                .flat_map(|piece| {
+                    if !overlaps.test(&piece, carved) {
+                        return vec![piece];
+                    }
+                    subtract(&piece.brush, &carved.brush)
+                        .into_iter()
+                        .map(|brush| CsgPiece {
+                            leaf: piece.leaf,
+                            brush,
+                        })
+                        .collect()
+                })
+                .collect()
+        }),
+        // The pieces of each set don't overlap so neither do their intersections.
+        CsgOp::Intersect => {
+            let mut result = Vec::new();
+            for piece in &set {
+                for kept in other {
+                    if !overlaps.test(piece, kept) {
+                        continue;
+                    }
+                    if let Some(brush) = intersect(&piece.brush, &kept.brush) {
+                        result.push(CsgPiece {
+                            leaf: piece.leaf,
+                            brush,
+                        });
+                    }
+                }
+            }
+            result
+        }
+    }
+}
+
+/// Merge a set of pieces in a single brush, see [`super::operations::merge_brushes`].
+pub fn merge_pieces(pieces: &[CsgPiece], overlaps: &mut Overlaps) -> BrushMesh {
+    merge_brushes_with(pieces, |a, b| overlaps.test(&pieces[a], &pieces[b]))
+}
+
+/// The evaluated convex brushes of a csg root or node, in its own space.
+/// The result after each of its children is kept so a clean node isn't evaluated again and only the
+/// children from the first changed one are combined again.
+#[derive(Component, Default)]
+pub struct CsgCache {
+    /// The children in the order they were combined, with the run and the length of their result.
+    children: Vec<(Entity, usize, usize)>,
+    /// The pieces after each run of added children.
+    /// Adding a child only pushes pieces so the result after any child of a run starts its pieces.
+    runs: Vec<Vec<CsgPiece>>,
+}
+
+impl CsgCache {
+    /// The pieces after all the children.
+    pub fn pieces(&self) -> &[CsgPiece] {
+        self.prefix(self.children.len())
+    }
+
+    /// The pieces after the first `count` children.
+    fn prefix(&self, count: usize) -> &[CsgPiece] {
+        match count.checked_sub(1).map(|last| self.children[last]) {
+            Some((_, run, len)) => &self.runs[run][..len],
+            None => &[],
+        }
+    }
+
+    /// Forget the results after the first `count` children.
+    fn truncate(&mut self, count: usize) {
+        match count.checked_sub(1).map(|last| self.children[last]) {
+            Some((_, run, len)) => {
+                self.runs.truncate(run + 1);
+                self.runs[run].truncate(len);
+            }
+            None => self.runs.clear(),
+        }
+        self.children.truncate(count);
+    }
+
+    /// Whether `children` are the children combined in the cache, in the same order.
+    fn combines<'a>(&self, children: impl Iterator<Item = &'a Entity>) -> bool {
+        children.eq(self.children.iter().map(|(child, ..)| child))
+    }
+
+    /// Combine the pieces of the next child with the current result.
+    fn push(&mut self, child: Entity, pieces: &[CsgPiece], op: CsgOp, overlaps: &mut Overlaps) {
+        let set = if op == CsgOp::Add {
+            let run = self.runs.pop().unwrap_or_default();
+            combine(run, pieces, op, overlaps)
+        } else {
+            // The current run is kept for the previous children.
+            combine(self.pieces().to_vec(), pieces, op, overlaps)
+        };
+        self.children.push((child, self.runs.len(), set.len()));
+        self.runs.push(set);
+    }
+}
+
+type CsgTreeQuery<'w, 's> =
+    Query<'w, 's, Option<&'static Parent>, Or<(With<CsgRoot>, With<CsgNode>)>>;
+
+/// Mark `node` and all its ancestors dirty.
+fn mark_dirty(commands: &mut Commands, node: Entity, tree: &CsgTreeQuery) {
+    let mut current = node;
+    while let Ok(parent) = tree.get(current) {
+        commands.entity(current).insert(Dirty);
+        let Some(parent) = parent else {
+            break;
+        };
+        current = parent.get();
+    }
+}
+
+/// Mark dirty the leaves whose brush, transform, operation or parent changed and the csg nodes
+/// whose result is outdated, up to their root.
+#[allow(clippy::too_many_arguments)]
+pub fn propagate_dirty(
+    mut commands: Commands,
+    mut brush_events: EventReader<AssetEvent<BrushMesh>>,
+    tree: CsgTreeQuery,
+    leaves: Query<(Entity, &Handle<BrushMesh>, Option<&Parent>), With<CsgLeaf>>,
+    moved: Query<
+        (Entity, Option<&Parent>),
+        (
+            Or<(With<CsgNode>, With<CsgLeaf>)>,
+            Or<(
+                Changed<Transform>,
+                Changed<CsgOp>,
+                Changed<Parent>,
+                (With<CsgLeaf>, Changed<Handle<BrushMesh>>),
+            )>,
+        ),
+    >,
+    regrouped: Query<
+        (Entity, &Children, Option<&CsgCache>),
+        (Or<(With<CsgRoot>, With<CsgNode>)>, Changed<Children>),
+    >,
+    csg_children: Query<(), Or<(With<CsgNode>, With<CsgLeaf>)>>,
+    reconfigured: Query<
+        Entity,
+        (
+            With<CsgRoot>,
+            Or<(Changed<CsgCollider>, Changed<CsgLightmap>)>,
+        ),
+    >,
+) {
+    // A moved node keeps its cache, its parent combines it again from its place.
+    for (entity, parent) in &moved {
+        commands.entity(entity).insert(Dirty);
+        if let Some(parent) = parent {
+            mark_dirty(&mut commands, parent.get(), &tree);
+        }
+    }
+    // Children were added, removed or reordered, the mesh parts of the roots aren't combined.
+    for (entity, children, cache) in &regrouped {
+        let children = children
+            .iter()
+            .filter(|child| csg_children.contains(**child));
+        if !cache.is_some_and(|cache| cache.combines(children)) {
+            mark_dirty(&mut commands, entity, &tree);
+        }
+    }
+    for entity in &reconfigured {
+        commands.entity(entity).insert(Dirty);
+    }
+
+    let changed: Vec<AssetId<BrushMesh>> = brush_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } | AssetEvent::Removed { id } => {
+                Some(*id)
+            }
+            _ => None,
+        })
+        .collect();
+    if changed.is_empty() {
+        return;
+    }
+    for (entity, brush, parent) in &leaves {
+        if !changed.contains(&brush.id()) {
+            continue;
+        }
+        commands.entity(entity).insert(Dirty);
+        if let Some(parent) = parent {
+            mark_dirty(&mut commands, parent.get(), &tree);
+        }
+    }
+}
+
+type CsgChildrenQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Transform,
+        Option<&'static CsgOp>,
+        Option<&'static Children>,
+        Option<&'static Handle<BrushMesh>>,
+        Has<CsgLeaf>,
+        Has<Dirty>,
+    ),
+    Or<(With<CsgNode>, With<CsgLeaf>)>,
+>;
+
+/// Evaluate the children of a dirty csg root or node in order, in its own space.
+/// The results of the clean children before the first changed one are taken from `cache`.
+/// Clean child nodes are taken from their cache, the new caches of the dirty ones are pushed in
+/// `results`.
+fn evaluate_children(
+    children: &[Entity],
+    mut cache: CsgCache,
+    nodes: &CsgChildrenQuery,
+    caches: &mut Query<&mut CsgCache>,
+    brushes: &Assets<BrushMesh>,
+    overlaps: &mut Overlaps,
+    results: &mut Vec<(Entity, CsgCache)>,
+) -> CsgCache {
+    // The roots also have the children rendering their result.
+    let children: Vec<Entity> = children
+        .iter()
+        .copied()
+        .filter(|child| nodes.contains(*child))
+        .collect();
+    let clean = children
+        .iter()
+        .zip(&cache.children)
+        .take_while(|(child, (cached, ..))| {
+            *child == cached && !nodes.get(**child).is_ok_and(|(.., is_dirty)| is_dirty)
+        })
+        .count();
+    cache.truncate(clean);
+
+    for child in &children[clean..] {
+        let Ok((transform, op, grand_children, brush, is_leaf, is_dirty)) = nodes.get(*child)
+        else {
+            continue;
+        };
+        let move_piece = |piece: &CsgPiece| CsgPiece {
+            leaf: piece.leaf,
+            brush: BakeTransform::from(*transform).apply(&piece.brush),
+        };
+        let pieces: Vec<CsgPiece> = if is_leaf {
+            // A leaf without its brush is combined as nothing.
+            brush
+                .and_then(|brush| brushes.get(brush))
+                .map(|brush| CsgPiece {
+                    leaf: *child,
+                    brush: BakeTransform::from(*transform).apply(brush),
+                })
+                .into_iter()
+                .collect()
+        } else {
+            let cached = match caches.get(*child) {
+                Ok(cache) if !is_dirty => Some(cache.pieces().iter().map(move_piece).collect()),
+                _ => None,
+            };
+            cached.unwrap_or_else(|| {
+                let node_cache = caches
+                    .get_mut(*child)
+                    .map(|mut cache| std::mem::take(&mut *cache))
+                    .unwrap_or_default();
+                let node_cache = evaluate_children(
+                    grand_children.map_or(&[], |grand_children| grand_children),
+                    node_cache,
+                    nodes,
+                    caches,
+                    brushes,
+                    overlaps,
+                    results,
+                );
+                let pieces = node_cache.pieces().iter().map(move_piece).collect();
+                results.push((*child, node_cache));
+                pieces
+            })
+        };
+        cache.push(*child, &pieces, op.copied().unwrap_or_default(), overlaps);
+    }
+    cache
+}
+
+/// A child of a csg root rendering the polygons of its result using one material.
+#[derive(Component)]
+pub struct CsgMeshPart(pub Option<BrushMaterial>);
+
+/// Set on a csg root changed on the previous frame, a root changing on consecutive frames is being
+/// dragged. Only the mesh of a dragged root is rebuilt, without cleaning it nor packing its
+/// lightmap, its brush and collider are rebuilt once it stops changing.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct CsgEditing {
+    /// Whether the brush and collider of the root are older than its mesh.
+    outdated: bool,
+}
+
+/// Evaluate the dirty csg roots and nodes, rebuild the brush and mesh of the roots, then clean the
+/// tree.
+#[allow(clippy::too_many_arguments)]
+pub fn undirty_tree(
+    mut commands: Commands,
+    mut brushes: ResMut<Assets<BrushMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut mats: ResMut<Assets<StandardMaterial>>,
+    mut brush_materials: ResMut<BrushMaterials>,
+    asset_server: Option<Res<AssetServer>>,
+    broadphase: Res<HierarchicalHashGrid>,
+    dirty_roots: Query<(Entity, Option<&Children>, Has<CsgEditing>), (With<CsgRoot>, With<Dirty>)>,
+    edited_roots: Query<(Entity, &CsgEditing), (With<CsgRoot>, Without<Dirty>)>,
+    dirty: Query<Entity, With<Dirty>>,
+    nodes: CsgChildrenQuery,
+    mut caches: Query<&mut CsgCache>,
+    roots: Query<
+        (
+            Option<&Handle<BrushMesh>>,
+            Option<&Children>,
+            Has<Visibility>,
+            Option<&CsgCollider>,
+            Option<&CsgLightmap>,
+        ),
+        With<CsgRoot>,
+    >,
+    parts: Query<(&CsgMeshPart, &Handle<Mesh>)>,
+) {
+    let mut overlaps = Overlaps::new(&broadphase);
+    let mut results = Vec::new();
+    let mut root_results = Vec::new();
+    for (root, children, dragged) in &dirty_roots {
+        let cache = caches
+            .get_mut(root)
+            .map(|mut cache| std::mem::take(&mut *cache))
+            .unwrap_or_default();
+        let cache = evaluate_children(
+            children.map_or(&[], |children| children),
+            cache,
+            &nodes,
+            &mut caches,
+            &brushes,
+            &mut overlaps,
+            &mut results,
+        );
+        commands
+            .entity(root)
+            .insert(CsgEditing { outdated: dragged });
+        root_results.push((root, cache, dragged));
+    }
+    // The roots dragged until the previous frame are rebuilt in full.
+    for (root, CsgEditing { outdated }) in &edited_roots {
+        commands.entity(root).remove::<CsgEditing>();
+        if !outdated {
+            continue;
+        }
+        if let Ok(mut cache) = caches.get_mut(root) {
+            root_results.push((root, std::mem::take(&mut *cache), false));
+        }
+    }
+    // The nodes are only read from their cache by their parent.
+    for (node, cache) in results {
+        commands.entity(node).insert(cache);
+    }
+
+    for (entity, cache, dragged) in root_results {
+        let merged = merge_pieces(cache.pieces(), &mut overlaps);
+        let Ok((brush_handle, children, has_visibility, collider, lightmap)) = roots.get(entity)
+        else {
+            continue;
+        };
+        let brush = if dragged {
+            merged
+        } else {
+            Cleanup::default().apply(&merged)
+        };
+        let collider = (!dragged).then(|| match collider.copied().unwrap_or_default() {
+            CsgCollider::Trimesh => TrimeshCollider.apply(&brush),
+            CsgCollider::Compound => {
+                compound_collider(cache.pieces().iter().map(|piece| &piece.brush))
+            }
+        });
+        commands.entity(entity).insert(cache);
+
+        // The roots are rendered with a part per material.
+        // The parts are updated in place so their entities and meshes are kept.
+        let mut new_parts = TriangulateMaterials {
+            smoothing: Smoothing::Groups,
+            lightmap: lightmap
+                .filter(|_| !dragged)
+                .map(|CsgLightmap(settings)| *settings),
+        }
+        .apply(&brush);
+        for part in children.into_iter().flatten() {
+            let Ok((CsgMeshPart(material), mesh_handle)) = parts.get(*part) else {
+                continue;
+            };
+            match new_parts.iter().position(|(other, _)| other == material) {
+                Some(index) => {
+                    if let Some(old_mesh) = meshes.get_mut(mesh_handle) {
+                        *old_mesh = new_parts.remove(index).1;
+                    }
+                    // The bounds of a mesh are only computed when missing.
+                    commands.entity(*part).remove::<Aabb>();
+                }
+                None => commands.entity(*part).despawn_recursive(),
+            }
+        }
+        let mut entity_commands = commands.entity(entity);
+        for (material, mesh) in new_parts {
+            let material_handle = brush_materials.get(material, asset_server.as_deref(), &mut mats);
+            entity_commands.with_children(|root| {
+                root.spawn((
+                    Name::new("Csg Mesh"),
+                    CsgMeshPart(material),
+                    PbrBundle {
+                        mesh: meshes.add(mesh),
+                        material: material_handle,
+                        ..default()
+                    },
+                ));
+            });
+        }
+        if !has_visibility {
+            entity_commands.insert(VisibilityBundle::default());
+        }
+        let Some(collider) = collider else {
+            continue;
+        };
+        match collider {
+            Some(collider) => {
+                entity_commands.insert((collider, environement_groups(), RigidBody::Fixed));
+            }
+            None => {
+                entity_commands.remove::<(Collider, CollisionGroups, RigidBody)>();
+            }
+        }
+        match brush_handle.and_then(|handle| brushes.get_mut(handle)) {
+            Some(old_brush) => *old_brush = brush,
+            None => {
+                entity_commands.insert(brushes.add(brush));
+            }
+        }
+    }
+
+    for entity in &dirty {
+        commands.entity(entity).remove::<Dirty>();
+    }
+}
+
+/// Draw the outline of the leaf brushes since only the result of the tree is rendered.
+pub fn draw_leaf_brushes(
+    mut gizmos: Gizmos,
+    brushes: Res<Assets<BrushMesh>>,
+    leaf_query: Query<(&GlobalTransform, &Handle<BrushMesh>, Option<&CsgOp>), With<CsgLeaf>>,
+) {
+    for (transform, brush, op) in &leaf_query {
+        let Some(brush) = brushes.get(brush) else {
+            continue;
+        };
+        let color = match op.copied().unwrap_or_default() {
+            CsgOp::Add => Color::CYAN,
+            CsgOp::Substract => Color::ORANGE,
+            CsgOp::Intersect => Color::PURPLE,
+        };
+        for edge in brush.edges() {
+            // Draw each edge once.
+            if edge.twin.is_some_and(|twin| twin < edge.id) {
+                continue;
+            }
+            let origin = transform.transform_point(brush.get_vertice(edge.origin).point);
+            let end = transform.transform_point(brush.get_vertice(edge.end).point);
+            gizmos.line(origin, end, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::transform::systems::{propagate_transforms, sync_simple_transforms};
+
+    use super::*;
+    use crate::editor::csg::{broadphase::update_broadphase, Brushable};
+
+    /// A world evaluating csg trees without rendering them.
+    fn csg_world() -> (World, Schedule) {
+        let mut world = World::new();
+        world.init_resource::<Assets<BrushMesh>>();
+        world.init_resource::<Assets<Mesh>>();
+        world.init_resource::<Assets<StandardMaterial>>();
+        world.init_resource::<BrushMaterials>();
+        world.init_resource::<Events<AssetEvent<BrushMesh>>>();
+        world.init_resource::<HierarchicalHashGrid>();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                sync_simple_transforms,
+                propagate_transforms,
+                update_broadphase,
+                propagate_dirty,
+                undirty_tree,
+            )
+                .chain(),
+        );
+        (world, schedule)
+    }
+
+    /// Run a frame and the one after it so the edited roots are rebuilt in full, then forget their
+    /// changes.
+    fn update(world: &mut World, schedule: &mut Schedule) {
+        schedule.run(world);
+        schedule.run(world);
+        world.clear_trackers();
+    }
+
+    fn spawn_cube(world: &mut World, size: f32, op: CsgOp, translation: Vec3) -> Entity {
+        let brush = Cuboid::from_size(Vec3::splat(size)).to_default_brush();
+        let brush = world.resource_mut::<Assets<BrushMesh>>().add(brush);
+        let transform = Transform::from_translation(translation);
+        world
+            .spawn((
+                CsgLeaf,
+                op,
+                brush,
+                TransformBundle::from_transform(transform),
+            ))
+            .id()
+    }
+
+    fn result_volume(world: &World, entity: Entity) -> f32 {
+        let brush = world.get::<Handle<BrushMesh>>(entity).unwrap();
+        world
+            .resource::<Assets<BrushMesh>>()
+            .get(brush)
+            .unwrap()
+            .volume()
+    }
+
+    #[test]
+    fn test_subtract_leaf() {
+        let (mut world, mut schedule) = csg_world();
+        let root = world.spawn((CsgRoot, TransformBundle::default())).id();
+        let cube = spawn_cube(&mut world, 2.0, CsgOp::Add, Vec3::ZERO);
+        let carved = spawn_cube(&mut world, 2.0, CsgOp::Substract, Vec3::ONE);
+        world.entity_mut(root).push_children(&[cube, carved]);
+        update(&mut world, &mut schedule);
+        assert!((result_volume(&world, root) - 7.0).abs() < 1e-4);
+
+        world.get_mut::<Transform>(carved).unwrap().translation = Vec3::X * 5.0;
+        update(&mut world, &mut schedule);
+        assert!((result_volume(&world, root) - 8.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_intersect_leaf() {
+        let (mut world, mut schedule) = csg_world();
+        let root = world.spawn((CsgRoot, TransformBundle::default())).id();
+        let cube = spawn_cube(&mut world, 2.0, CsgOp::Add, Vec3::ZERO);
+        let kept = spawn_cube(&mut world, 2.0, CsgOp::Intersect, Vec3::new(1.0, 1.0, 0.0));
+        world.entity_mut(root).push_children(&[cube, kept]);
+        update(&mut world, &mut schedule);
+        assert!((result_volume(&world, root) - 2.0).abs() < 1e-4);
+        let physics = |world: &World| {
+            let root = world.entity(root);
+            [
+                root.contains::<Collider>(),
+                root.contains::<CollisionGroups>(),
+                root.contains::<RigidBody>(),
+            ]
+        };
+        assert_eq!(physics(&world), [true; 3]);
+
+        // A leading intersection has nothing to intersect with.
+        world.entity_mut(root).remove_children(&[cube]);
+        update(&mut world, &mut schedule);
+        assert_eq!(result_volume(&world, root), 0.0);
+        assert_eq!(physics(&world), [false; 3]);
+    }
+
+    #[test]
+    fn test_lightmap_setting() {
+        let (mut world, mut schedule) = csg_world();
+        let root = world.spawn((CsgRoot, TransformBundle::default())).id();
+        let cube = spawn_cube(&mut world, 2.0, CsgOp::Add, Vec3::ZERO);
+        world.entity_mut(root).push_children(&[cube]);
+        let lightmapped = |world: &World| -> Vec<bool> {
+            let meshes = world.resource::<Assets<Mesh>>();
+            world
+                .get::<Children>(root)
+                .into_iter()
+                .flatten()
+                .filter(|child| world.get::<CsgMeshPart>(**child).is_some())
+                .map(|part| {
+                    let mesh = meshes.get(world.get::<Handle<Mesh>>(*part).unwrap());
+                    mesh.unwrap().contains_attribute(Mesh::ATTRIBUTE_UV_1)
+                })
+                .collect()
+        };
+        // The lightmap is only packed when baking.
+        update(&mut world, &mut schedule);
+        assert_eq!(lightmapped(&world), [false]);
+        world.entity_mut(root).insert(CsgLightmap::default());
+        update(&mut world, &mut schedule);
+        assert_eq!(lightmapped(&world), [true]);
+    }
+
+    #[test]
+    fn test_node_evaluated_first() {
+        let (mut world, mut schedule) = csg_world();
+        let root = world.spawn((CsgRoot, TransformBundle::default())).id();
+        let cube = spawn_cube(&mut world, 2.0, CsgOp::Add, Vec3::ZERO);
+        let node = world
+            .spawn((CsgNode, CsgOp::Intersect, TransformBundle::default()))
+            .id();
+        let overlapping = spawn_cube(&mut world, 2.0, CsgOp::Add, Vec3::X);
+        let apart = spawn_cube(&mut world, 2.0, CsgOp::Add, Vec3::X * 5.0);
+        world.entity_mut(node).push_children(&[overlapping, apart]);
+        world.entity_mut(root).push_children(&[cube, node]);
+        update(&mut world, &mut schedule);
+        // The cube is intersected with both cubes of the node, not only the first one.
+        assert!((result_volume(&world, root) - 4.0).abs() < 1e-4);
+    }
+
+    /// The entities marked dirty by `change`, cleaned afterward.
+    fn dirtied(
+        world: &mut World,
+        schedule: &mut Schedule,
+        change: impl FnOnce(&mut World),
+    ) -> HashSet<Entity> {
+        change(world);
+        update(world, schedule);
+        let dirty: HashSet<Entity> = world
+            .query_filtered::<Entity, With<Dirty>>()
+            .iter(world)
+            .collect();
+        for entity in &dirty {
+            world.entity_mut(*entity).remove::<Dirty>();
+        }
+        dirty
+    }
+
+    #[test]
+    fn test_propagate_dirty() {
+        let (mut world, mut schedule) = csg_world();
+        let root = world.spawn((CsgRoot, TransformBundle::default())).id();
+        let other_root = world.spawn((CsgRoot, TransformBundle::default())).id();
+        let node = world
+            .spawn((CsgNode, CsgOp::Add, TransformBundle::default()))
+            .id();
+        let [a, b, c, d] = [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z]
+            .map(|translation| spawn_cube(&mut world, 1.0, CsgOp::Add, translation));
+        world.entity_mut(node).push_children(&[b, c]);
+        world.entity_mut(root).push_children(&[a, node]);
+        world.entity_mut(other_root).push_children(&[d]);
+        update(&mut world, &mut schedule);
+        assert_eq!(world.query::<&Dirty>().iter(&world).count(), 0);
+
+        let mut propagate = Schedule::default();
+        propagate.add_systems(propagate_dirty);
+        // A new system sees everything as changed.
+        dirtied(&mut world, &mut propagate, |_| {});
+        let branch = HashSet::from([b, node, root]);
+        let moved = dirtied(&mut world, &mut propagate, |world| {
+            world.get_mut::<Transform>(b).unwrap().translation.y = 1.0;
+        });
+        assert_eq!(moved, branch);
+        let changed_op = dirtied(&mut world, &mut propagate, |world| {
+            *world.get_mut::<CsgOp>(b).unwrap() = CsgOp::Substract;
+        });
+        assert_eq!(changed_op, branch);
+        let brush = world.get::<Handle<BrushMesh>>(b).unwrap().id();
+        let edited = dirtied(&mut world, &mut propagate, |world| {
+            world.send_event(AssetEvent::Modified { id: brush });
+        });
+        assert_eq!(edited, branch);
+        let parts = dirtied(&mut world, &mut propagate, |world| {
+            world.spawn(CsgMeshPart(None)).set_parent(root);
+        });
+        assert!(parts.is_empty());
+        let reparented = dirtied(&mut world, &mut propagate, |world| {
+            world.entity_mut(c).set_parent(other_root);
+        });
+        assert_eq!(reparented, HashSet::from([c, node, root, other_root]));
+    }
+
+    #[test]
+    fn test_dragged_root() {
+        let (mut world, mut schedule) = csg_world();
+        let root = world.spawn((CsgRoot, TransformBundle::default())).id();
+        // A grid of a few hundred cubes apart from each other.
+        let cubes: Vec<Entity> = (0..300)
+            .map(|index| {
+                let translation = Vec3::new((index % 20) as f32, 0.0, (index / 20) as f32) * 2.0;
+                spawn_cube(&mut world, 1.0, CsgOp::Add, translation)
+            })
+            .collect();
+        world.entity_mut(root).push_children(&cubes);
+        update(&mut world, &mut schedule);
+        assert!((result_volume(&world, root) - 300.0).abs() < 1e-2);
+
+        let vertices = |world: &World| -> usize {
+            let meshes = world.resource::<Assets<Mesh>>();
+            world
+                .get::<Children>(root)
+                .unwrap()
+                .iter()
+                .filter_map(|part| world.get::<Handle<Mesh>>(*part))
+                .map(|mesh| meshes.get(mesh).unwrap().count_vertices())
+                .sum()
+        };
+        // Run a frame and tell if the collider was rebuilt.
+        let mut frame = |world: &mut World| {
+            world.clear_trackers();
+            schedule.run(world);
+            world
+                .entity(root)
+                .get_ref::<Collider>()
+                .unwrap()
+                .is_changed()
+        };
+        let move_cube = |world: &mut World, x: f32| {
+            world.get_mut::<Transform>(cubes[0]).unwrap().translation.x = x;
+        };
+        // The first frame of a drag can't be told from a single change.
+        move_cube(&mut world, 0.25);
+        assert!(frame(&mut world));
+        let apart = vertices(&world);
+        // Then only the mesh follows the cube, it overlaps its neighbour by half.
+        move_cube(&mut world, 1.5);
+        assert!(!frame(&mut world));
+        assert_ne!(vertices(&world), apart);
+        assert!((result_volume(&world, root) - 300.0).abs() < 1e-2);
+        assert!(world.get::<CsgEditing>(root).unwrap().outdated);
+
+        // The brush and collider are rebuilt once the cube stops moving.
+        assert!(frame(&mut world));
+        assert!((result_volume(&world, root) - 299.5).abs() < 1e-2);
+        assert!(!frame(&mut world));
+        assert!(world.get::<CsgEditing>(root).is_none());
+    }
+
+    #[test]
+    fn test_clean_siblings_reused() {
+        let (mut world, mut schedule) = csg_world();
+        let root = world.spawn((CsgRoot, TransformBundle::default())).id();
+        let first = spawn_cube(&mut world, 2.0, CsgOp::Add, Vec3::ZERO);
+        let node = world
+            .spawn((CsgNode, CsgOp::Add, TransformBundle::default()))
+            .id();
+        let nested = spawn_cube(&mut world, 2.0, CsgOp::Add, Vec3::X * 5.0);
+        let last = spawn_cube(&mut world, 2.0, CsgOp::Add, Vec3::Z * 5.0);
+        world.entity_mut(node).push_children(&[nested]);
+        world.entity_mut(root).push_children(&[first, node, last]);
+        update(&mut world, &mut schedule);
+        assert!((result_volume(&world, root) - 24.0).abs() < 1e-4);
+
+        // Without its brush the first leaf can only be in the result if it isn't evaluated again.
+        let brush = world.get::<Handle<BrushMesh>>(first).unwrap().id();
+        world.resource_mut::<Assets<BrushMesh>>().remove(brush);
+        world.get_mut::<Transform>(last).unwrap().translation.z = 10.0;
+        schedule.run(&mut world);
+        assert!((result_volume(&world, root) - 24.0).abs() < 1e-4);
+        assert!(!world
+            .entity(node)
+            .get_ref::<CsgCache>()
+            .unwrap()
+            .is_changed());
+        assert!(world
+            .entity(root)
+            .get_ref::<CsgCache>()
+            .unwrap()
+            .is_changed());
+        assert_eq!(world.query::<&Dirty>().iter(&world).count(), 0);
+    }
+}
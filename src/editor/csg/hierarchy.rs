@@ -1,7 +1,7 @@
 use bevy::{hierarchy::HierarchyEvent, utils::HashSet};
 use crate::prelude::*;
 
-use super::{CsgNode, CsgRoot};
+use super::{evaluate::CsgMeshPart, CsgNode, CsgRoot};
 
 #[derive(Event)]
 pub enum CsgHierarchyEvent {
@@ -25,11 +25,15 @@ pub fn filter_csg_hierarchy_events(
     mut h_ui_event: EventWriter<CsgHierarchyEvent>,
     mut h_events: EventReader<HierarchyEvent>,
     csg_internal_node: Query<Entity, Or<(With<CsgRoot>, With<CsgNode>)>>,
+    mesh_parts: Query<(), With<CsgMeshPart>>,
 ) {
     let csg_nodes: HashSet<Entity> = csg_internal_node.iter().collect();
     for hierarchy_event in h_events.read() {
         match hierarchy_event {
-            HierarchyEvent::ChildAdded { child, parent } if csg_nodes.contains(parent) => {
+            // The meshes of the roots are not part of the csg tree.
+            HierarchyEvent::ChildAdded { child, parent }
+                if csg_nodes.contains(parent) && !mesh_parts.contains(*child) =>
+            {
                 h_ui_event.send(CsgHierarchyEvent::ChildAdded {
                     child: *child,
                     parent: *parent,
@@ -0,0 +1,51 @@
+use bevy::render::texture::{
+    ImageAddressMode, ImageLoaderSettings, ImageSampler, ImageSamplerDescriptor,
+};
+use bevy::utils::HashMap;
+
+use crate::prelude::*;
+
+use super::brush_mesh::BrushMaterial;
+
+/// The materials of the brush surfaces, shared by all the brushes using them.
+#[derive(Resource, Default)]
+pub struct BrushMaterials(HashMap<Option<BrushMaterial>, Handle<StandardMaterial>>);
+
+impl BrushMaterials {
+    /// The material for `material`, created the first time it is asked for.
+    /// Without an asset server the textures aren't loaded.
+    pub fn get(
+        &mut self,
+        material: Option<BrushMaterial>,
+        asset_server: Option<&AssetServer>,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Handle<StandardMaterial> {
+        self.0
+            .entry(material)
+            .or_insert_with(|| {
+                let Some(material) = material else {
+                    return materials.add(StandardMaterial::default());
+                };
+                let texture = asset_server.map(|asset_server| {
+                    // The uvs of the brushes go past 1 to tile the textures.
+                    asset_server.load_with_settings(
+                        material.texture_path(),
+                        |settings: &mut ImageLoaderSettings| {
+                            settings.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+                                address_mode_u: ImageAddressMode::Repeat,
+                                address_mode_v: ImageAddressMode::Repeat,
+                                ..ImageSamplerDescriptor::default()
+                            });
+                        },
+                    )
+                });
+                materials.add(StandardMaterial {
+                    base_color_texture: texture,
+                    perceptual_roughness: 1.0,
+                    reflectance: 0.0,
+                    ..default()
+                })
+            })
+            .clone()
+    }
+}
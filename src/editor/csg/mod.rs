@@ -1,3 +1,5 @@
+use bevy::transform::TransformSystem;
+
 use crate::prelude::*;
 
 pub mod convert;
@@ -7,6 +9,10 @@ pub use convert::Brushable;
 pub mod brush_mesh;
 use brush_mesh::*;
 
+pub mod broadphase;
+pub mod evaluate;
+pub mod materials;
+
 use self::operations::{BrushMeshOperation, Triangulate};
 
 pub mod operations;
@@ -16,31 +22,102 @@ pub struct CsgPlugin;
 impl Plugin for CsgPlugin {
     fn build(&self, app: &mut App) {
         app.init_asset::<BrushMesh>()
+            .register_type::<convert::Stairs>()
+            .register_type::<convert::Arch>()
+            .register_type::<convert::Pipe>()
+            .register_type::<convert::QuarterPipe>()
+            .init_resource::<broadphase::HierarchicalHashGrid>()
+            .init_resource::<materials::BrushMaterials>()
             .add_systems(Startup, setup_csg_root)
-            .add_systems(Update, on_brush_added);
+            .add_systems(
+                Update,
+                (
+                    on_brush_added,
+                    validate_brushes,
+                    draw_invalid_brushes,
+                    evaluate::draw_leaf_brushes,
+                    convert::regenerate_brush_groups::<convert::Stairs>,
+                    convert::regenerate_brush_groups::<convert::Arch>,
+                    convert::regenerate_brush_groups::<convert::Pipe>,
+                    convert::regenerate_brush_groups::<convert::QuarterPipe>,
+                ),
+            )
+            // The tree is evaluated once the leaves have their global transform for the broadphase.
+            .add_systems(
+                PostUpdate,
+                (
+                    broadphase::update_broadphase,
+                    evaluate::propagate_dirty,
+                    evaluate::undirty_tree,
+                )
+                    .chain()
+                    .after(TransformSystem::TransformPropagate),
+            );
     }
 }
 
 #[derive(Component)]
 pub struct CsgRoot;
 
+/// How the result of a csg root collides with the players, a trimesh of its brush by default.
+#[derive(Component, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CsgCollider {
+    #[default]
+    Trimesh,
+    /// A compound of the convex pieces of the result.
+    Compound,
+}
+
+/// Set on a csg root to lay its result out in a lightmap atlas when baking the map.
+/// The atlas is packed again on each change of the tree so it is left off while editing.
+#[derive(Component, Default, Clone, Copy, PartialEq, Debug)]
+pub struct CsgLightmap(pub operations::LightmapSettings);
+
 #[derive(Component)]
 pub struct CsgNode;
 
 #[derive(Component)]
 pub struct CsgLeaf;
 
+/// Set on the csg entities whose evaluated result is outdated.
 #[derive(Component)]
 #[component(storage = "SparseSet")]
-struct Dirty;
+pub struct Dirty;
+
+/// Set on a leaf whose brush failed [`BrushMesh::validate`].
+#[derive(Component, Deref)]
+pub struct InvalidBrush(Vec<BrushMeshError>);
 
-// Csg operation is add by default set this component on a brush to put it in substract mode.
-#[derive(Component, Default, Clone, Copy)]
+/// Csg operation is add by default set this component on a brush to put it in substract mode.
+/// The children of a node are combined in order, each one with the result of its previous siblings.
+/// A `CsgNode` is evaluated on its own first and its result is then combined with its siblings
+/// using the node's operation.
+/// The first child is combined with nothing so a leading `Substract` or `Intersect` yields nothing.
+#[derive(Component, Default, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum CsgOp {
     #[default]
     Add,
     Substract,
-    // TODO: Add Intesect.
+    /// Only keep what is inside both the previous siblings and this child.
+    Intersect,
+}
+
+impl CsgOp {
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Add => Self::Substract,
+            Self::Substract => Self::Intersect,
+            Self::Intersect => Self::Add,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Substract => "-",
+            Self::Intersect => "&",
+        }
+    }
 }
 
 fn setup_csg_root(mut commands: Commands, mut brushes: ResMut<Assets<BrushMesh>>) {
@@ -56,6 +133,7 @@ fn on_brush_added(
     brushes: Res<Assets<BrushMesh>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut mats: ResMut<Assets<StandardMaterial>>,
+    mut brush_materials: ResMut<materials::BrushMaterials>,
     leaf_query: Query<(Entity, Option<&Name>, &Handle<BrushMesh>), (Added<Handle<BrushMesh>>, With<CsgLeaf>, With<CsgOp>)>,
 ) {
     for (entity, name, brush) in &leaf_query {
@@ -67,61 +145,74 @@ fn on_brush_added(
             entity.insert(Name::new("Unnamed"));
         };
         let mesh = 
-            meshes.add(Triangulate.apply(brushes.get(brush).unwrap()));
-        let mat = mats.add( StandardMaterial::default());
+            meshes.add(Triangulate::default().apply(brushes.get(brush).unwrap()));
+        let mat = brush_materials.get(None, None, &mut mats);
+        // The leaf is drawn through the evaluated mesh of its csg root.
         entity.insert((
             mesh,
             mat,
-            Visibility::default(),
+            Visibility::Hidden,
             InheritedVisibility::default(),
             ViewVisibility::default(),
         ));
     }
 }
 
-// fn propagate_dirty(mut commands: Commands, dirtied: Entity, dirty_query: Query<(Entity, &Parent), Or<(With<CsgRoot>, With<CsgNode>)>>, dirty_leaf: Query<&Parent, With<CsgLeaf>>) {
-//     let Ok(mut dirty_parent) = dirty_leaf.get(dirtied) else {
-//         error!("Dirty entity not found {dirtied:?}.");
-//         return;
-//     };
-//     while let Ok((to_dirty, parent)) = dirty_query.get(**dirty_parent) {
-//         commands.entity(dirtied).insert(Dirty);
-//         dirty_parent = parent;
-//     }
-// }
-//
-// pub fn undirty_tree(
-//     mut commands: Commands,
-//     mut brushes: ResMut<Assets<BrushMesh>>,
-//     mut dirty_root: Query<(&Children, &Handle<BrushMesh>), (With<CsgRoot>, With<Dirty>)>,
-//     nodes: Query<(&Transform, Option<&Children>, &Handle<BrushMesh>, Option<&Dirty>, &CsgOp), Or<(With<CsgNode>, With<CsgLeaf>)>>,
-// ) {
-//     let Ok((top_childrens, brush)) = dirty_root.get_single() else {
-//         return;
-//     };
-//     for child in top_childrens {
-//         undirty_node(*child, commands, brushes,  nodes);
-//     }
-//     nodes.iter_many(top_childrens);
-// }
-//
-// fn undirty_node<'a>(
-//     node: Entity, 
-//     mut commands: Commands,
-//     mut burshes: ResMut<Assets<BrushMesh>>,
-//     nodes: Query<(&Transform, Option<&Children>, &Handle<BrushMesh>, Option<&Dirty>, &CsgOp), Or<(With<CsgNode>, With<CsgLeaf>)>>,
-// ) -> Option<(Transform, &'a Handle<BrushMesh>, CsgOp)> {
-//     let brushes: Vec<(&Handle<BrushMesh>, CsgOp)> = Vec::new();
-//     let (transform, childrens, brush, dirty, op) = nodes.get(node).ok()?; 
-//
-//     // If the node is not dirty or it is a leaf there is nothing to do. 
-//     if dirty.is_none() || childrens.is_none() {
-//         return Some((*transform, brush, *op));
-//     }
-//
-//     
-//
-//     return Some((*transform, brush, *op));
-// }
-//
-//
+fn validate_brushes(
+    mut commands: Commands,
+    mut brush_events: EventReader<AssetEvent<BrushMesh>>,
+    brushes: Res<Assets<BrushMesh>>,
+    leaf_query: Query<(Entity, Option<&Name>, Ref<Handle<BrushMesh>>), With<CsgLeaf>>,
+) {
+    let changed: Vec<AssetId<BrushMesh>> = brush_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    for (entity, name, brush) in &leaf_query {
+        if !brush.is_added() && !changed.contains(&brush.id()) {
+            continue;
+        }
+        let Some(brush_mesh) = brushes.get(&*brush) else {
+            continue;
+        };
+        // Flat brushes like planes are open on purpose.
+        let is_flat = brush_mesh.is_flat();
+        let errors: Vec<BrushMeshError> = brush_mesh
+            .validate()
+            .err()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|error| !(is_flat && matches!(error, BrushMeshError::OpenEdge(_))))
+            .collect();
+        if errors.is_empty() {
+            commands.entity(entity).remove::<InvalidBrush>();
+        } else {
+            let name = name.map_or("Unnamed", |name| name.as_str());
+            for error in &errors {
+                warn!("Brush {name} ({entity:?}) is invalid: {error}.");
+            }
+            commands.entity(entity).insert(InvalidBrush(errors));
+        }
+    }
+}
+
+fn draw_invalid_brushes(
+    mut gizmos: Gizmos,
+    brushes: Res<Assets<BrushMesh>>,
+    invalid_query: Query<(&GlobalTransform, &Handle<BrushMesh>), With<InvalidBrush>>,
+) {
+    for (transform, brush) in &invalid_query {
+        let Some(brush) = brushes.get(brush) else {
+            continue;
+        };
+        for edge in brush.edges() {
+            let origin = transform.transform_point(brush.get_vertice(edge.origin).point);
+            let end = transform.transform_point(brush.get_vertice(edge.end).point);
+            gizmos.line(origin, end, Color::RED);
+        }
+    }
+}
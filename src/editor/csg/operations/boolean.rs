@@ -0,0 +1,433 @@
+use bevy::utils::HashMap;
+
+use crate::common::geometry::APlane3d;
+use crate::editor::csg::brush_mesh::{
+    BrushMesh, PlaneId, Surface, VerticeId, BRUSH_EPSILON,
+};
+use crate::prelude::*;
+
+use super::{BakeTransform, BrushMeshOperation, PlaneClip};
+
+/// Add `other` to the brush.
+/// The result is the brush followed by the convex pieces of `other` that are outside of it.
+pub struct Union<'a> {
+    pub other: &'a BrushMesh,
+    /// The transform from the space of `other` to the space of the brush.
+    pub transform: Transform,
+}
+
+/// Carve `other` out of the brush.
+/// The result is the convex pieces of the brush that are outside of `other`.
+pub struct Subtract<'a> {
+    pub other: &'a BrushMesh,
+    /// The transform from the space of `other` to the space of the brush.
+    pub transform: Transform,
+}
+
+/// Keep the part of the brush that is inside `other`.
+/// The result is empty or holds a single convex brush.
+pub struct Intersect<'a> {
+    pub other: &'a BrushMesh,
+    /// The transform from the space of `other` to the space of the brush.
+    pub transform: Transform,
+}
+
+impl BrushMeshOperation for Union<'_> {
+    type Out = Vec<BrushMesh>;
+
+    fn apply(self, brush: &BrushMesh) -> Self::Out {
+        let other = BakeTransform::from(self.transform).apply(self.other);
+        let mut result = vec![brush.clone()];
+        result.extend(subtract(&other, brush));
+        result
+    }
+}
+
+impl BrushMeshOperation for Subtract<'_> {
+    type Out = Vec<BrushMesh>;
+
+    fn apply(self, brush: &BrushMesh) -> Self::Out {
+        subtract(
+            brush,
+            &BakeTransform::from(self.transform).apply(self.other),
+        )
+    }
+}
+
+impl BrushMeshOperation for Intersect<'_> {
+    type Out = Vec<BrushMesh>;
+
+    fn apply(self, brush: &BrushMesh) -> Self::Out {
+        intersect(
+            brush,
+            &BakeTransform::from(self.transform).apply(self.other),
+        )
+        .into_iter()
+        .collect()
+    }
+}
+
+/// The planes of the brush that bound a polygon as clipping planes.
+fn clip_planes(brush: &BrushMesh) -> impl Iterator<Item = APlane3d> + '_ {
+    brush
+        .planes()
+        .filter(|plane| !plane.polygons().is_empty())
+        .filter_map(|plane| {
+            let normal = Direction3d::new(plane.normal).ok()?;
+            Some(APlane3d::new(plane.point, normal))
+        })
+}
+
+/// The clipping planes of the brush with the surface of their first polygon.
+fn clip_faces(brush: &BrushMesh) -> impl Iterator<Item = (APlane3d, Surface)> + '_ {
+    brush
+        .planes()
+        .filter_map(|plane| {
+            let polygon = *plane.polygons().first()?;
+            Some((plane, polygon))
+        })
+        .filter_map(|(plane, polygon)| {
+            let normal = Direction3d::new(plane.normal).ok()?;
+            let surface = brush.get_polygon(polygon).surface;
+            Some((APlane3d::new(plane.point, normal), surface))
+        })
+}
+
+/// The convex pieces of `brush` outside of `other`.
+/// The faces carved in the brush take the surface of `other`.
+pub(crate) fn subtract(brush: &BrushMesh, other: &BrushMesh) -> Vec<BrushMesh> {
+    // An empty brush has no inside.
+    if other.polygons().next().is_none() {
+        return vec![brush.clone()];
+    }
+    let mut pieces = Vec::new();
+    let mut remaining = brush.clone();
+    for (plane, surface) in clip_faces(other) {
+        let clipped = PlaneClip(plane).apply(&remaining).with_cap_surface(surface);
+        if let Some(front) = clipped.front {
+            pieces.push(front);
+        }
+        match clipped.back {
+            Some(back) => remaining = back,
+            // Nothing of the brush is left inside of `other`.
+            None => return pieces,
+        }
+    }
+    // What remains is inside `other` and is carved out.
+    pieces
+}
+
+/// The part of `brush` inside of `other`.
+/// The faces cut in the brush take the surface of `other`.
+pub(crate) fn intersect(brush: &BrushMesh, other: &BrushMesh) -> Option<BrushMesh> {
+    // An empty brush has no inside.
+    other.polygons().next()?;
+    let mut remaining = brush.clone();
+    for (plane, surface) in clip_faces(other) {
+        remaining = PlaneClip(plane)
+            .apply(&remaining)
+            .with_cap_surface(surface)
+            .back?;
+    }
+    Some(remaining)
+}
+
+/// Merge a set of convex brushes in a single brush made of their visible polygons.
+/// The parts of the polygons inside of another brush or facing another brush are removed.
+pub fn merge_brushes(brushes: &[BrushMesh]) -> BrushMesh {
+    merge_brushes_with(brushes, |_, _| true)
+}
+
+/// Merge a set of convex brushes like [`merge_brushes`], only clipping the polygons of a brush
+/// with the brushes for which `overlaps` returns true.
+pub fn merge_brushes_with<B: AsRef<BrushMesh>>(
+    brushes: &[B],
+    mut overlaps: impl FnMut(usize, usize) -> bool,
+) -> BrushMesh {
+    let mut merged = MergedBrush::new();
+    for (index, brush) in brushes.iter().enumerate() {
+        let brush = brush.as_ref();
+        let others: Vec<(usize, &BrushMesh)> = brushes
+            .iter()
+            .enumerate()
+            .filter(|(other_index, _)| *other_index != index && overlaps(index, *other_index))
+            .map(|(other_index, other)| (other_index, other.as_ref()))
+            .collect();
+        for poly in brush.polygons() {
+            let plane = brush.get_plane(poly.plane);
+            let points: Vec<Vec3> = poly.verticies().map(|vertice| vertice.point).collect();
+            let mut fragments = vec![points];
+            for (other_index, other) in &others {
+                fragments = fragments
+                    .into_iter()
+                    .flat_map(|fragment| {
+                        outside_fragments(fragment, plane.normal, other, index < *other_index)
+                    })
+                    .collect();
+            }
+            for fragment in fragments {
+                merged.add_polygon(
+                    &fragment,
+                    (index, poly.plane),
+                    (plane.normal, plane.point),
+                    poly.surface,
+                );
+            }
+        }
+    }
+    merged.brush
+}
+
+/// The pieces of a convex polygon that are outside of a convex brush.
+/// The part of the polygon lying on a polygon of the brush facing the same way is kept if
+/// `keep_coplanar`, the part on a polygon facing the other way is always removed.
+fn outside_fragments(
+    polygon: Vec<Vec3>,
+    normal: Vec3,
+    brush: &BrushMesh,
+    keep_coplanar: bool,
+) -> Vec<Vec<Vec3>> {
+    let mut fragments = Vec::new();
+    let mut remaining = polygon;
+    let mut coplanar = None;
+    for plane in clip_planes(brush) {
+        let (front, back) = split_polygon(&remaining, &plane);
+        match (front, back) {
+            (None, None) => coplanar = Some(normal.dot(*plane.normal) > 0.0),
+            (Some(front), None) => {
+                fragments.push(front);
+                return fragments;
+            }
+            (front, Some(back)) => {
+                fragments.extend(front);
+                remaining = back;
+            }
+        }
+    }
+    if coplanar == Some(true) && keep_coplanar {
+        fragments.push(remaining);
+    }
+    fragments
+}
+
+/// Split a convex polygon by a plane into its front and back parts.
+/// A polygon lying on the plane has neither.
+fn split_polygon(
+    polygon: &[Vec3],
+    plane: &APlane3d,
+) -> (Option<Vec<Vec3>>, Option<Vec<Vec3>>) {
+    let distances: Vec<f32> = polygon
+        .iter()
+        .map(|point| (*point - plane.origin).dot(*plane.normal))
+        .collect();
+    let mut front = Vec::new();
+    let mut back = Vec::new();
+    let len = polygon.len();
+    for i in 0..len {
+        let j = (i + 1) % len;
+        let (point, distance) = (polygon[i], distances[i]);
+        if distance > BRUSH_EPSILON {
+            front.push(point);
+        } else if distance < -BRUSH_EPSILON {
+            back.push(point);
+        } else {
+            front.push(point);
+            back.push(point);
+        }
+        let next = distances[j];
+        if (distance > BRUSH_EPSILON && next < -BRUSH_EPSILON)
+            || (distance < -BRUSH_EPSILON && next > BRUSH_EPSILON)
+        {
+            let split = point.lerp(polygon[j], distance / (distance - next));
+            front.push(split);
+            back.push(split);
+        }
+    }
+    let has_front = distances.iter().any(|distance| *distance > BRUSH_EPSILON);
+    let has_back = distances.iter().any(|distance| *distance < -BRUSH_EPSILON);
+    (
+        (has_front && front.len() >= 3).then_some(front),
+        (has_back && back.len() >= 3).then_some(back),
+    )
+}
+
+/// A brush built from a soup of polygons, welding their vertices together.
+struct MergedBrush {
+    brush: BrushMesh,
+    vertices: HashMap<IVec3, VerticeId>,
+    planes: HashMap<(usize, PlaneId), PlaneId>,
+}
+
+impl MergedBrush {
+    fn new() -> Self {
+        Self {
+            brush: BrushMesh::empty(),
+            vertices: HashMap::new(),
+            planes: HashMap::new(),
+        }
+    }
+
+    fn add_polygon(
+        &mut self,
+        points: &[Vec3],
+        source_plane: (usize, PlaneId),
+        (normal, point): (Vec3, Vec3),
+        surface: Surface,
+    ) {
+        let mut vertices: Vec<VerticeId> = Vec::with_capacity(points.len());
+        for point in points {
+            let key = (*point / BRUSH_EPSILON).round().as_ivec3();
+            let id = *self
+                .vertices
+                .entry(key)
+                .or_insert_with(|| self.brush.add_vertice(*point));
+            if vertices.last() != Some(&id) && vertices.first() != Some(&id) {
+                vertices.push(id);
+            }
+        }
+        if vertices.len() < 3 {
+            return;
+        }
+        let plane = *self
+            .planes
+            .entry(source_plane)
+            .or_insert_with(|| self.brush.add_plane(normal, point));
+        let polygon = self.brush.add_polygon_on_plane(&vertices, plane);
+        self.brush.set_polygon_surface(polygon, surface);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::csg::{brush_mesh::TextureProjection, convert::Slope, Brushable};
+
+    #[test]
+    fn test_subtract_corner() {
+        let pieces = Subtract {
+            other: &Cuboid::from_size(Vec3::splat(2.0)).to_default_brush(),
+            transform: Transform::from_translation(Vec3::ONE),
+        }
+        .apply(&Cuboid::from_size(Vec3::splat(2.0)).to_default_brush());
+        let volume: f32 = pieces.iter().map(BrushMesh::volume).sum();
+        assert!((volume - 7.0).abs() < 1e-4);
+        for piece in &pieces {
+            assert_eq!(piece.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_subtract_surfaces() {
+        let mut other = Cuboid::from_size(Vec3::splat(2.0)).to_default_brush();
+        let surface = Surface {
+            texture: TextureProjection {
+                rotation: 1.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for polygon in 0..other.polygons().count() {
+            other.set_polygon_surface(polygon, surface);
+        }
+        let pieces = Subtract {
+            other: &other,
+            transform: Transform::from_translation(Vec3::ONE),
+        }
+        .apply(&Cuboid::from_size(Vec3::splat(2.0)).to_default_brush());
+        // The faces left from the brush keep their surface, the carved ones get the other one.
+        for piece in &pieces {
+            for poly in piece.polygons() {
+                let normal = piece.get_plane(poly.plane).normal;
+                let center = poly.verticies().map(|vertice| vertice.point).sum::<Vec3>()
+                    / poly.verticies.len() as f32;
+                let outside = center.abs().max_element() > 1.0 - 1e-4
+                    && center.dot(normal) > 1.0 - 1e-4;
+                let expected = if outside {
+                    Surface::default()
+                } else {
+                    surface
+                };
+                assert_eq!(poly.surface, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_subtract_disjoint_and_contained() {
+        let far = Subtract {
+            other: &Cuboid::from_size(Vec3::splat(1.0)).to_default_brush(),
+            transform: Transform::from_translation(Vec3::X * 5.0),
+        }
+        .apply(&Cuboid::from_size(Vec3::splat(1.0)).to_default_brush());
+        assert_eq!(far.len(), 1);
+        assert!((far[0].volume() - 1.0).abs() < 1e-4);
+
+        let inside = Subtract {
+            other: &Cuboid::from_size(Vec3::splat(4.0)).to_default_brush(),
+            transform: Transform::IDENTITY,
+        }
+        .apply(&Cuboid::from_size(Vec3::splat(1.0)).to_default_brush());
+        assert!(inside.is_empty());
+    }
+
+    #[test]
+    fn test_intersect() {
+        let result = Intersect {
+            other: &Cuboid::from_size(Vec3::splat(2.0)).to_default_brush(),
+            transform: Transform::from_translation(Vec3::new(1.0, 1.0, 0.0)),
+        }
+        .apply(&Cuboid::from_size(Vec3::splat(2.0)).to_default_brush());
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].validate(), Ok(()));
+        assert!((result[0].volume() - 2.0).abs() < 1e-4);
+
+        let disjoint = Intersect {
+            other: &Cuboid::from_size(Vec3::splat(1.0)).to_default_brush(),
+            transform: Transform::from_translation(Vec3::Y * 3.0),
+        }
+        .apply(&Cuboid::from_size(Vec3::splat(1.0)).to_default_brush());
+        assert!(disjoint.is_empty());
+    }
+
+    #[test]
+    fn test_union_slope() {
+        let slope: BrushMesh = Slope {
+            length: 2.0,
+            width: 1.0,
+            height: 1.0,
+        }
+        .into();
+        let pieces = Union {
+            other: &slope,
+            transform: Transform::from_translation(Vec3::NEG_Y * 0.5),
+        }
+        .apply(&Cuboid::from_size(Vec3::splat(1.0)).to_default_brush());
+        let volume: f32 = pieces.iter().map(BrushMesh::volume).sum();
+        // The part of the slope inside the cube is a prism of volume 0.5.
+        assert!((volume - (1.0 + slope.volume() - 0.5)).abs() < 1e-4);
+        for piece in &pieces {
+            assert_eq!(piece.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_merge_removes_hidden_faces() {
+        let pieces = Union {
+            other: &Cuboid::from_size(Vec3::splat(1.0)).to_default_brush(),
+            transform: Transform::from_translation(Vec3::X),
+        }
+        .apply(&Cuboid::from_size(Vec3::splat(1.0)).to_default_brush());
+        let merged = merge_brushes(&pieces);
+        assert_eq!(merged.polygons().count(), 10);
+        assert!((merged.volume() - 2.0).abs() < 1e-4);
+
+        let overlapping = Union {
+            other: &Cuboid::from_size(Vec3::splat(1.0)).to_default_brush(),
+            transform: Transform::from_translation(Vec3::splat(0.5)),
+        }
+        .apply(&Cuboid::from_size(Vec3::splat(1.0)).to_default_brush());
+        let merged = merge_brushes(&overlapping);
+        let volume: f32 = overlapping.iter().map(BrushMesh::volume).sum();
+        assert!((merged.volume() - volume).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,339 @@
+use bevy::utils::{HashMap, HashSet};
+
+use crate::editor::csg::brush_mesh::{
+    BrushMesh, PlaneId, Surface, VerticeId, BRUSH_EPSILON,
+};
+use crate::prelude::*;
+
+use super::BrushMeshOperation;
+
+/// Tidy up a brush: weld the vertices that are close to each other, merge the adjacent polygons
+/// lying on the same plane, remove the vertices in the middle of a straight edge and drop
+/// everything left unused.
+/// Polygons are only merged when they have the same surface and the result stays convex.
+pub struct Cleanup {
+    /// The distance under which vertices are welded and planes are merged.
+    pub tolerance: f32,
+}
+
+impl Default for Cleanup {
+    fn default() -> Self {
+        Self {
+            tolerance: BRUSH_EPSILON,
+        }
+    }
+}
+
+/// A polygon being cleaned, its vertices are ids of the source brush.
+struct CleanPolygon {
+    plane: usize,
+    verticies: Vec<VerticeId>,
+    surface: Surface,
+}
+
+impl BrushMeshOperation for Cleanup {
+    type Out = BrushMesh;
+
+    fn apply(self, brush: &BrushMesh) -> Self::Out {
+        let points: Vec<Vec3> = brush.vertices().map(|vertice| vertice.point).collect();
+        let welded = self.weld(&points);
+        let (planes, plane_map) = self.merge_planes(brush);
+
+        let mut polygons: Vec<CleanPolygon> = brush
+            .polygons()
+            .filter_map(|poly| {
+                let mut verticies: Vec<VerticeId> = Vec::with_capacity(poly.verticies.len());
+                for vertice in &poly.verticies {
+                    let vertice = welded[*vertice];
+                    if verticies.last() != Some(&vertice) {
+                        verticies.push(vertice);
+                    }
+                }
+                while verticies.len() > 1 && verticies.first() == verticies.last() {
+                    verticies.pop();
+                }
+                (verticies.len() >= 3).then(|| CleanPolygon {
+                    plane: plane_map[&poly.plane],
+                    verticies,
+                    surface: poly.surface,
+                })
+            })
+            .collect();
+
+        self.merge_polygons(&mut polygons, &planes, &points);
+        self.remove_collinear(&mut polygons, &points);
+
+        // Compact the brush keeping only what is used.
+        let mut result = BrushMesh::empty();
+        let mut vertice_map: HashMap<VerticeId, VerticeId> = HashMap::new();
+        let mut new_planes: HashMap<usize, PlaneId> = HashMap::new();
+        for polygon in polygons {
+            if polygon.verticies.len() < 3 {
+                continue;
+            }
+            let surface = polygon.surface;
+            let verticies: Vec<VerticeId> = polygon
+                .verticies
+                .iter()
+                .map(|vertice| {
+                    *vertice_map
+                        .entry(*vertice)
+                        .or_insert_with(|| result.add_vertice(points[*vertice]))
+                })
+                .collect();
+            let plane = *new_planes.entry(polygon.plane).or_insert_with(|| {
+                let (normal, point) = planes[polygon.plane];
+                result.add_plane(normal, point)
+            });
+            let polygon = result.add_polygon_on_plane(&verticies, plane);
+            result.set_polygon_surface(polygon, surface);
+        }
+        result
+    }
+}
+
+impl Cleanup {
+    /// Map each vertice to the first vertice close enough to it.
+    fn weld(&self, points: &[Vec3]) -> Vec<VerticeId> {
+        let cell_size = self.tolerance.max(f32::EPSILON);
+        let mut cells: HashMap<IVec3, Vec<VerticeId>> = HashMap::new();
+        let mut welded = Vec::with_capacity(points.len());
+        for (id, point) in points.iter().enumerate() {
+            let cell = (*point / cell_size).floor().as_ivec3();
+            let mut found = None;
+            'search: for x in -1..=1 {
+                for y in -1..=1 {
+                    for z in -1..=1 {
+                        let Some(candidates) = cells.get(&(cell + IVec3::new(x, y, z))) else {
+                            continue;
+                        };
+                        if let Some(other) = candidates
+                            .iter()
+                            .find(|other| points[**other].distance(*point) <= self.tolerance)
+                        {
+                            found = Some(*other);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+            match found {
+                Some(other) => welded.push(other),
+                None => {
+                    cells.entry(cell).or_default().push(id);
+                    welded.push(id);
+                }
+            }
+        }
+        welded
+    }
+
+    /// Merge the planes with the same normal and offset.
+    /// Returns the merged planes as normal and point and the map from the source planes.
+    fn merge_planes(&self, brush: &BrushMesh) -> (Vec<(Vec3, Vec3)>, HashMap<PlaneId, usize>) {
+        let mut planes: Vec<(Vec3, Vec3)> = Vec::new();
+        let mut plane_map = HashMap::new();
+        for (id, plane) in brush.planes().enumerate() {
+            let normal = plane.normal.normalize_or_zero();
+            let existing = planes.iter().position(|(other_normal, other_point)| {
+                normal.cross(*other_normal).length() <= self.tolerance
+                    && normal.dot(*other_normal) > 0.0
+                    && (plane.point - *other_point).dot(*other_normal).abs() <= self.tolerance
+            });
+            let index = existing.unwrap_or_else(|| {
+                planes.push((normal, plane.point));
+                planes.len() - 1
+            });
+            plane_map.insert(id, index);
+        }
+        (planes, plane_map)
+    }
+
+    /// Merge the polygons of a same plane sharing edges until no merge is possible.
+    fn merge_polygons(
+        &self,
+        polygons: &mut Vec<CleanPolygon>,
+        planes: &[(Vec3, Vec3)],
+        points: &[Vec3],
+    ) {
+        let mut by_plane: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (index, polygon) in polygons.iter().enumerate() {
+            by_plane.entry(polygon.plane).or_default().push(index);
+        }
+        let mut removed = vec![false; polygons.len()];
+        for (plane, indices) in by_plane {
+            let mut merged = true;
+            while merged {
+                merged = false;
+                for (i, a) in indices.iter().enumerate() {
+                    if removed[*a] {
+                        continue;
+                    }
+                    for b in &indices[i + 1..] {
+                        if removed[*b] || polygons[*a].surface != polygons[*b].surface {
+                            continue;
+                        }
+                        let Some(verticies) = self.merge_pair(
+                            &polygons[*a].verticies,
+                            &polygons[*b].verticies,
+                            planes[plane],
+                            points,
+                        ) else {
+                            continue;
+                        };
+                        polygons[*a].verticies = verticies;
+                        removed[*b] = true;
+                        merged = true;
+                    }
+                }
+            }
+        }
+        let mut index = 0;
+        polygons.retain(|_| {
+            index += 1;
+            !removed[index - 1]
+        });
+    }
+
+    /// The polygon made of `a` and `b` if they share edges and their union is a convex polygon.
+    fn merge_pair(
+        &self,
+        a: &[VerticeId],
+        b: &[VerticeId],
+        (normal, point): (Vec3, Vec3),
+        points: &[Vec3],
+    ) -> Option<Vec<VerticeId>> {
+        // Planes close enough to be merged can still hold vertices too far from each other.
+        let on_plane = a
+            .iter()
+            .chain(b)
+            .all(|vertice| (points[*vertice] - point).dot(normal).abs() <= self.tolerance);
+        if !on_plane {
+            return None;
+        }
+        let edges = |polygon: &[VerticeId]| -> Vec<(VerticeId, VerticeId)> {
+            (0..polygon.len())
+                .map(|i| (polygon[i], polygon[(i + 1) % polygon.len()]))
+                .collect()
+        };
+        let (edges_a, edges_b) = (edges(a), edges(b));
+        let set_a: HashSet<(VerticeId, VerticeId)> = edges_a.iter().copied().collect();
+        let set_b: HashSet<(VerticeId, VerticeId)> = edges_b.iter().copied().collect();
+        let remaining: Vec<(VerticeId, VerticeId)> = edges_a
+            .iter()
+            .filter(|(u, v)| !set_b.contains(&(*v, *u)))
+            .chain(edges_b.iter().filter(|(u, v)| !set_a.contains(&(*v, *u))))
+            .copied()
+            .collect();
+        if remaining.len() == edges_a.len() + edges_b.len() {
+            return None;
+        }
+
+        // The remaining edges must form a single loop.
+        let mut next: HashMap<VerticeId, VerticeId> = HashMap::new();
+        for (u, v) in &remaining {
+            if next.insert(*u, *v).is_some() {
+                return None;
+            }
+        }
+        let start = remaining.first()?.0;
+        let mut verticies = vec![start];
+        let mut current = next[&start];
+        while current != start {
+            if verticies.len() > remaining.len() {
+                return None;
+            }
+            verticies.push(current);
+            current = *next.get(&current)?;
+        }
+        if verticies.len() != remaining.len() {
+            return None;
+        }
+
+        let len = verticies.len();
+        let convex = (0..len).all(|i| {
+            let previous = points[verticies[(i + len - 1) % len]];
+            let current = points[verticies[i]];
+            let next = points[verticies[(i + 1) % len]];
+            let turn = (current - previous)
+                .normalize_or_zero()
+                .cross((next - current).normalize_or_zero());
+            turn.dot(normal) >= -self.tolerance
+        });
+        convex.then_some(verticies)
+    }
+
+    /// Remove the vertices linked to only two other vertices lying on a line with them.
+    fn remove_collinear(&self, polygons: &mut [CleanPolygon], points: &[Vec3]) {
+        let mut neighbours: HashMap<VerticeId, HashSet<VerticeId>> = HashMap::new();
+        for polygon in polygons.iter() {
+            let len = polygon.verticies.len();
+            for i in 0..len {
+                let (u, v) = (polygon.verticies[i], polygon.verticies[(i + 1) % len]);
+                neighbours.entry(u).or_default().insert(v);
+                neighbours.entry(v).or_default().insert(u);
+            }
+        }
+        let collinear: HashSet<VerticeId> = neighbours
+            .iter()
+            .filter(|(_, around)| around.len() == 2)
+            .filter(|(vertice, around)| {
+                let mut around = around.iter();
+                let (previous, next) = (around.next().unwrap(), around.next().unwrap());
+                let to_vertice = (points[**vertice] - points[*previous]).normalize_or_zero();
+                let to_next = (points[*next] - points[**vertice]).normalize_or_zero();
+                to_vertice.cross(to_next).length() <= self.tolerance
+                    && to_vertice.dot(to_next) > 0.0
+            })
+            .map(|(vertice, _)| *vertice)
+            .collect();
+        for polygon in polygons.iter_mut() {
+            polygon
+                .verticies
+                .retain(|vertice| !collinear.contains(vertice));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::csg::{operations::merge_brushes, operations::Union, Brushable};
+
+    #[test]
+    fn test_merge_coplanar_polygons() {
+        let cube = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        let pieces = Union {
+            other: &cube,
+            transform: Transform::from_translation(Vec3::X),
+        }
+        .apply(&cube);
+        let merged = merge_brushes(&pieces);
+        let cleaned = Cleanup::default().apply(&merged);
+        assert_eq!(cleaned.polygons().count(), 6);
+        assert_eq!(cleaned.vertices().count(), 8);
+        assert_eq!(cleaned.planes().count(), 6);
+        assert_eq!(cleaned.validate(), Ok(()));
+        assert!((cleaned.volume() - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_weld_vertices() {
+        // Every polygon of the cube gets its own vertices.
+        let cube = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        let mut soup = BrushMesh::empty();
+        for (i, poly) in cube.polygons().enumerate() {
+            let offset = i as f32 * 1e-5;
+            let verticies =
+                soup.extend_verticies(poly.verticies().map(|vertice| vertice.point + offset));
+            let plane = cube.get_plane(poly.plane);
+            let plane = soup.add_plane(plane.normal, plane.point);
+            soup.add_polygon_on_plane(&verticies, plane);
+        }
+        assert_eq!(soup.vertices().count(), 24);
+        let cleaned = Cleanup::default().apply(&soup);
+        assert_eq!(cleaned.vertices().count(), 8);
+        assert_eq!(cleaned.polygons().count(), 6);
+        assert_eq!(cleaned.validate(), Ok(()));
+    }
+}
@@ -1,33 +1,287 @@
-use common::geometry::APlane3d;
+use bevy::utils::HashMap;
 
-
-use crate::csg::BrushMesh;
+use crate::common::geometry::APlane3d;
+use crate::editor::csg::brush_mesh::{
+    BrushMesh, PlaneId, PolygonId, Surface, VerticeId, BRUSH_EPSILON,
+};
+use crate::prelude::*;
 
 use super::BrushMeshOperation;
 
-pub struct PlaneClip(APlane3d);
+/// Split a convex brush in two along a plane.
+pub struct PlaneClip(pub APlane3d);
+
+/// The pieces of a brush split by a [`PlaneClip`].
+/// A piece is `None` if the brush has no volume on its side of the plane.
+pub struct ClippedBrush {
+    /// The piece in front of the plane.
+    pub front: Option<BrushMesh>,
+    /// The piece behind the plane.
+    pub back: Option<BrushMesh>,
+}
+
+impl ClippedBrush {
+    /// Set the surface of the polygons closing the pieces along the plane, if the brush was cut.
+    pub fn with_cap_surface(mut self, surface: Surface) -> Self {
+        // A cut brush has both pieces and their caps are their last polygon.
+        if let (Some(front), Some(back)) = (&mut self.front, &mut self.back) {
+            for piece in [front, back] {
+                let cap = piece.polygons().count() - 1;
+                piece.set_polygon_surface(cap, surface);
+            }
+        }
+        self
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Front,
+    Back,
+    On,
+}
+
+/// A vertice of a piece, either from the clipped brush or created on one of its edges.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum PieceVertice {
+    Vertice(VerticeId),
+    Split(VerticeId, VerticeId),
+}
 
 impl BrushMeshOperation for PlaneClip {
-    type Out = BrushMesh;
+    type Out = ClippedBrush;
 
-    fn apply(self, _brush: &BrushMesh) -> Self::Out {
-        let result = BrushMesh::empty();
+    fn apply(self, brush: &BrushMesh) -> Self::Out {
         let PlaneClip(plane) = self;
-        for poly in _brush.polygons() {
-            let plane = _brush.get_plane(poly.plane);
-            // If the planes are colinear there is no clipping.
-            if (plane.normal.cross(normal).length_squared() < f32::EPSILON) && point != plane.point
-            {
-                if plane.point.intersect_plane(normal ,point) {
+        let distances: Vec<f32> = brush
+            .vertices()
+            .map(|vertice| (vertice.point - plane.origin).dot(*plane.normal))
+            .collect();
+        let sides: Vec<Side> = distances
+            .iter()
+            .map(|distance| {
+                if *distance > BRUSH_EPSILON {
+                    Side::Front
+                } else if *distance < -BRUSH_EPSILON {
+                    Side::Back
+                } else {
+                    Side::On
+                }
+            })
+            .collect();
+
+        // If the brush is on one side of the plane, or touches it with a face, there is nothing to cut.
+        if !sides.contains(&Side::Front) {
+            return ClippedBrush {
+                front: None,
+                back: Some(brush.clone()),
+            };
+        }
+        if !sides.contains(&Side::Back) {
+            return ClippedBrush {
+                front: Some(brush.clone()),
+                back: None,
+            };
+        }
+
+        let split_point = |a: VerticeId, b: VerticeId| {
+            // Always interpolate in the same direction so both polygons of an edge get the same point.
+            let (a, b) = (a.min(b), a.max(b));
+            let t = distances[a] / (distances[a] - distances[b]);
+            brush
+                .get_vertice(a)
+                .point
+                .lerp(brush.get_vertice(b).point, t)
+        };
 
+        let mut front = Piece::new(brush);
+        let mut back = Piece::new(brush);
+        let mut cap = Vec::new();
+        for poly in brush.polygons() {
+            let mut front_polygon = Vec::new();
+            let mut back_polygon = Vec::new();
+            let len = poly.verticies.len();
+            for i in 0..len {
+                let current = poly.verticies[i];
+                let next = poly.verticies[(i + 1) % len];
+                let vertice = PieceVertice::Vertice(current);
+                match sides[current] {
+                    Side::Front => front_polygon.push(vertice),
+                    Side::Back => back_polygon.push(vertice),
+                    Side::On => {
+                        front_polygon.push(vertice);
+                        back_polygon.push(vertice);
+                        if !cap.contains(&vertice) {
+                            cap.push(vertice);
+                        }
+                    }
+                }
+                if matches!(
+                    (sides[current], sides[next]),
+                    (Side::Front, Side::Back) | (Side::Back, Side::Front)
+                ) {
+                    let split = PieceVertice::Split(current.min(next), current.max(next));
+                    front_polygon.push(split);
+                    back_polygon.push(split);
+                    if !cap.contains(&split) {
+                        cap.push(split);
+                    }
                 }
-                continue;
             }
-
-            for _edge in poly.edges() {
+            if front_polygon.len() >= 3 {
+                front.add_polygon(&front_polygon, poly.id, &split_point);
+            }
+            if back_polygon.len() >= 3 {
+                back.add_polygon(&back_polygon, poly.id, &split_point);
             }
         }
-        unimplemented!()
+
+        // Close both pieces with the section of the brush by the plane.
+        let cap_points: Vec<Vec3> = cap
+            .iter()
+            .map(|vertice| match vertice {
+                PieceVertice::Vertice(id) => brush.get_vertice(*id).point,
+                PieceVertice::Split(a, b) => split_point(*a, *b),
+            })
+            .collect();
+        let center = cap_points.iter().sum::<Vec3>() / cap_points.len() as f32;
+        let (u, v) = plane.normal.any_orthonormal_pair();
+        let mut order: Vec<usize> = (0..cap.len()).collect();
+        order.sort_by(|a, b| {
+            let angle = |i: &usize| {
+                let offset = cap_points[*i] - center;
+                offset.dot(v).atan2(offset.dot(u))
+            };
+            angle(a).total_cmp(&angle(b))
+        });
+        let back_cap: Vec<PieceVertice> = order.iter().map(|i| cap[*i]).collect();
+        let front_cap: Vec<PieceVertice> = back_cap.iter().rev().copied().collect();
+        back.add_cap(&back_cap, *plane.normal, center, &split_point);
+        front.add_cap(&front_cap, -*plane.normal, center, &split_point);
+
+        ClippedBrush {
+            front: Some(front.brush),
+            back: Some(back.brush),
+        }
     }
 }
 
+/// A brush being built from the polygons of another brush.
+struct Piece<'b> {
+    source: &'b BrushMesh,
+    brush: BrushMesh,
+    vertices: HashMap<PieceVertice, VerticeId>,
+    planes: HashMap<PlaneId, PlaneId>,
+}
+
+impl<'b> Piece<'b> {
+    fn new(source: &'b BrushMesh) -> Self {
+        Self {
+            source,
+            brush: BrushMesh::empty(),
+            vertices: HashMap::new(),
+            planes: HashMap::new(),
+        }
+    }
+
+    fn vertice(
+        &mut self,
+        vertice: PieceVertice,
+        split_point: &impl Fn(VerticeId, VerticeId) -> Vec3,
+    ) -> VerticeId {
+        if let Some(id) = self.vertices.get(&vertice) {
+            return *id;
+        }
+        let point = match vertice {
+            PieceVertice::Vertice(id) => self.source.get_vertice(id).point,
+            PieceVertice::Split(a, b) => split_point(a, b),
+        };
+        let id = self.brush.add_vertice(point);
+        self.vertices.insert(vertice, id);
+        id
+    }
+
+    /// Add the part of the `source` polygon going around `vertices`.
+    fn add_polygon(
+        &mut self,
+        vertices: &[PieceVertice],
+        source: PolygonId,
+        split_point: &impl Fn(VerticeId, VerticeId) -> Vec3,
+    ) {
+        let vertices: Vec<VerticeId> = vertices
+            .iter()
+            .map(|vertice| self.vertice(*vertice, split_point))
+            .collect();
+        let source = self.source.get_polygon(source);
+        let plane = source.plane;
+        let plane = match self.planes.get(&plane) {
+            Some(plane) => *plane,
+            None => {
+                let source = self.source.get_plane(plane);
+                let id = self.brush.add_plane(source.normal, source.point);
+                self.planes.insert(plane, id);
+                id
+            }
+        };
+        let polygon = self.brush.add_polygon_on_plane(&vertices, plane);
+        self.brush.set_polygon_surface(polygon, source.surface);
+    }
+
+    fn add_cap(
+        &mut self,
+        vertices: &[PieceVertice],
+        normal: Vec3,
+        point: Vec3,
+        split_point: &impl Fn(VerticeId, VerticeId) -> Vec3,
+    ) {
+        let vertices: Vec<VerticeId> = vertices
+            .iter()
+            .map(|vertice| self.vertice(*vertice, split_point))
+            .collect();
+        let plane = self.brush.add_plane(normal, point);
+        self.brush.add_polygon_on_plane(&vertices, plane);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::csg::Brushable;
+
+    /// Clip a 2x2x2 cube and check the pieces are valid and share its volume.
+    fn clip_cube(origin: Vec3, normal: Direction3d) -> (Option<BrushMesh>, Option<BrushMesh>) {
+        let cube = Cuboid::from_size(Vec3::splat(2.0)).to_default_brush();
+        let ClippedBrush { front, back } = PlaneClip(APlane3d::new(origin, normal)).apply(&cube);
+        let mut volume = 0.0;
+        for piece in front.iter().chain(&back) {
+            assert_eq!(piece.validate(), Ok(()));
+            volume += piece.volume();
+        }
+        assert!((volume - 8.0).abs() < 1e-4);
+        (front, back)
+    }
+
+    #[test]
+    fn test_clip_middle() {
+        let (front, back) = clip_cube(Vec3::ZERO, Direction3d::X);
+        let (front, back) = (front.unwrap(), back.unwrap());
+        assert_eq!(front.polygons().count(), 6);
+        assert!((front.volume() - back.volume()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_clip_coplanar() {
+        let (front, back) = clip_cube(Vec3::X, Direction3d::X);
+        assert!(front.is_none() && back.is_some());
+        let (front, back) = clip_cube(Vec3::X, Direction3d::NEG_X);
+        assert!(front.is_some() && back.is_none());
+    }
+
+    #[test]
+    fn test_clip_outside() {
+        let (front, back) = clip_cube(Vec3::X * -3.0, Direction3d::X);
+        assert!(front.is_some() && back.is_none());
+        let (front, back) = clip_cube(Vec3::X * 3.0, Direction3d::X);
+        assert!(front.is_none() && back.is_some());
+    }
+}
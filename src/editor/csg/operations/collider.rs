@@ -0,0 +1,83 @@
+use crate::common::physics::{
+    col_layers,
+    prelude::{Collider, CollisionGroups},
+};
+use crate::editor::csg::brush_mesh::{BrushMesh, BRUSH_EPSILON};
+use crate::prelude::*;
+
+use super::{polygon_triangles, BrushMeshOperation};
+
+/// The collision groups of the colliders made from brushes, the same as the rest of the level.
+pub fn environement_groups() -> CollisionGroups {
+    CollisionGroups::new(col_layers::ENVIRONEMENT, col_layers::PLAYERS)
+}
+
+/// A convex hull collider around the verticies of a convex brush.
+/// Nothing is made when the brush is flat or empty.
+pub struct ConvexHullCollider;
+
+impl BrushMeshOperation for ConvexHullCollider {
+    type Out = Option<Collider>;
+
+    fn apply(self, brush: &BrushMesh) -> Self::Out {
+        if brush.volume() <= BRUSH_EPSILON {
+            return None;
+        }
+        Collider::convex_hull(&brush.positions())
+    }
+}
+
+/// A triangle mesh collider following the polygons of any brush, like the result of a csg tree.
+/// Nothing is made when the brush is empty.
+pub struct TrimeshCollider;
+
+impl BrushMeshOperation for TrimeshCollider {
+    type Out = Option<Collider>;
+
+    fn apply(self, brush: &BrushMesh) -> Self::Out {
+        let mut indices = Vec::new();
+        for poly in brush.polygons() {
+            let (verticies, triangles) = polygon_triangles(brush, poly.id);
+            for triangle in triangles {
+                indices.push(triangle.map(|corner| verticies[corner] as u32));
+            }
+        }
+        if indices.is_empty() {
+            return None;
+        }
+        Some(Collider::trimesh(brush.positions(), indices))
+    }
+}
+
+/// A compound of the convex hulls of disjoint convex brushes, like the pieces of a csg tree.
+/// Nothing is made when none of the brushes has a volume.
+pub fn compound_collider<'b>(brushes: impl IntoIterator<Item = &'b BrushMesh>) -> Option<Collider> {
+    let shapes: Vec<(Vec3, Quat, Collider)> = brushes
+        .into_iter()
+        .filter_map(|brush| ConvexHullCollider.apply(brush))
+        .map(|collider| (Vec3::ZERO, Quat::IDENTITY, collider))
+        .collect();
+    if shapes.is_empty() {
+        return None;
+    }
+    Some(Collider::compound(shapes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::csg::Brushable;
+
+    #[test]
+    fn test_brush_colliders() {
+        let cube = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        assert!(ConvexHullCollider.apply(&cube).is_some());
+        assert!(TrimeshCollider.apply(&cube).is_some());
+        assert!(compound_collider([&cube, &cube]).is_some());
+
+        let empty = BrushMesh::empty();
+        assert!(ConvexHullCollider.apply(&empty).is_none());
+        assert!(TrimeshCollider.apply(&empty).is_none());
+        assert!(compound_collider([&empty]).is_none());
+    }
+}
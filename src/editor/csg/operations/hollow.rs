@@ -0,0 +1,125 @@
+use crate::common::geometry::APlane3d;
+use crate::editor::csg::brush_mesh::{BrushMesh, Surface, BRUSH_EPSILON};
+use crate::prelude::*;
+
+use super::{BrushMeshOperation, PlaneClip};
+
+/// The side of the faces of a brush the walls of a [`Hollow`] grow on.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HollowDirection {
+    /// The walls are inside the brush, which keeps its outer shape.
+    #[default]
+    Inward,
+    /// The walls are around the brush, which becomes the empty room.
+    Outward,
+}
+
+/// Turn a convex brush in a shell made of one wall of `thickness` per face.
+/// The walls are disjoint convex brushes, in the order of the planes of the brush.
+/// Both sides of a wall take the surface of the face it is made from.
+pub struct Hollow {
+    pub thickness: f32,
+    pub direction: HollowDirection,
+}
+
+impl BrushMeshOperation for Hollow {
+    type Out = Vec<BrushMesh>;
+
+    fn apply(self, brush: &BrushMesh) -> Self::Out {
+        let (planes, surfaces): (Vec<APlane3d>, Vec<Surface>) = brush
+            .planes()
+            .filter_map(|plane| {
+                let polygon = *plane.polygons().first()?;
+                Some((plane, polygon))
+            })
+            .filter_map(|(plane, polygon)| {
+                let normal = Direction3d::new(plane.normal).ok()?;
+                let surface = brush.get_polygon(polygon).surface;
+                Some((APlane3d::new(plane.point, normal), surface))
+            })
+            .unzip();
+        let offset = |distance: f32| -> Vec<APlane3d> {
+            planes
+                .iter()
+                .map(|plane| APlane3d::new(plane.origin + *plane.normal * distance, plane.normal))
+                .collect()
+        };
+
+        // Each wall is cut from what is left of the outer brush along the inner side of its face.
+        let (mut remaining, inner) = match self.direction {
+            HollowDirection::Inward => (brush.clone(), offset(-self.thickness)),
+            HollowDirection::Outward => match BrushMesh::from_planes(&offset(self.thickness)) {
+                Ok(mut outer) => {
+                    for polygon in 0..outer.polygons().count() {
+                        let normal = outer.get_plane(outer.get_polygon(polygon).plane).normal;
+                        if let Some(face) = planes
+                            .iter()
+                            .position(|plane| plane.normal.dot(normal) > 1.0 - BRUSH_EPSILON)
+                        {
+                            outer.set_polygon_surface(polygon, surfaces[face]);
+                        }
+                    }
+                    (outer, planes)
+                }
+                Err(error) => {
+                    warn!("Couldn't grow the brush to hollow it: {error}.");
+                    return Vec::new();
+                }
+            },
+        };
+        let mut walls = Vec::with_capacity(inner.len());
+        for (plane, surface) in inner.into_iter().zip(surfaces) {
+            let clipped = PlaneClip(plane).apply(&remaining).with_cap_surface(surface);
+            walls.extend(clipped.front);
+            match clipped.back {
+                Some(back) => remaining = back,
+                // The walls are thick enough to fill the whole brush.
+                None => break,
+            }
+        }
+        walls
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::csg::Brushable;
+
+    #[test]
+    fn test_hollow_cube() {
+        let cube = Cuboid::from_size(Vec3::splat(2.0)).to_default_brush();
+        let walls = Hollow {
+            thickness: 0.25,
+            direction: HollowDirection::Inward,
+        }
+        .apply(&cube);
+        assert_eq!(walls.len(), 6);
+        let volume: f32 = walls.iter().map(BrushMesh::volume).sum();
+        assert!((volume - (8.0 - 1.5f32.powi(3))).abs() < 1e-4);
+        for wall in &walls {
+            assert_eq!(wall.validate(), Ok(()));
+        }
+
+        let walls = Hollow {
+            thickness: 0.5,
+            direction: HollowDirection::Outward,
+        }
+        .apply(&cube);
+        assert_eq!(walls.len(), 6);
+        let volume: f32 = walls.iter().map(BrushMesh::volume).sum();
+        assert!((volume - (27.0 - 8.0)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_hollow_too_thick() {
+        let cube = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        let walls = Hollow {
+            thickness: 1.0,
+            direction: HollowDirection::Inward,
+        }
+        .apply(&cube);
+        let volume: f32 = walls.iter().map(BrushMesh::volume).sum();
+        assert!((volume - 1.0).abs() < 1e-4);
+    }
+}
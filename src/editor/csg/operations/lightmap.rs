@@ -0,0 +1,306 @@
+use bevy::utils::HashMap;
+
+use crate::editor::csg::brush_mesh::{BrushMesh, PolygonId, BRUSH_EPSILON};
+use crate::prelude::*;
+
+use super::{plane_axes, BrushMeshOperation};
+
+/// How the lightmap of a brush is laid out.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LightmapSettings {
+    /// The number of texels per unit of length.
+    pub texel_density: f32,
+    /// The side of the square atlas, in texels.
+    pub atlas_size: u32,
+    /// The texels left empty around each chart so the charts don't bleed on each other.
+    pub padding: u32,
+}
+
+impl Default for LightmapSettings {
+    fn default() -> Self {
+        Self {
+            texel_density: 16.0,
+            atlas_size: 1024,
+            padding: 2,
+        }
+    }
+}
+
+/// Connected coplanar polygons laid flat in the lightmap atlas.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LightmapChart {
+    /// The polygons of the chart, in increasing order.
+    pub polygons: Vec<PolygonId>,
+    /// The axes of the plane of the chart.
+    axes: (Vec3, Vec3),
+    /// The lowest corner of the polygons along the axes.
+    origin: Vec2,
+    /// The size of the polygons along the axes.
+    extent: Vec2,
+    /// The size of the chart in texels, padding included.
+    pub size: UVec2,
+    /// The lowest corner of the chart in the atlas, in texels.
+    pub position: UVec2,
+}
+
+/// Where the polygons of a brush are in a lightmap atlas.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LightmapAtlas {
+    pub settings: LightmapSettings,
+    /// The density of the charts, lower than the one of the settings when they didn't fit.
+    pub texel_density: f32,
+    pub charts: Vec<LightmapChart>,
+    charts_by_polygon: HashMap<PolygonId, usize>,
+}
+
+impl LightmapAtlas {
+    /// The lightmap uv of a point of a polygon, in the `[0, 1]` range.
+    /// The polygons without a chart, like the holes, are at the origin of the atlas.
+    pub fn uv(&self, polygon: PolygonId, point: Vec3) -> Vec2 {
+        let Some(chart) = self
+            .charts_by_polygon
+            .get(&polygon)
+            .map(|chart| &self.charts[*chart])
+        else {
+            return Vec2::ZERO;
+        };
+        let (u, v) = chart.axes;
+        let texel = chart.position.as_vec2()
+            + Vec2::splat(self.settings.padding as f32)
+            + (Vec2::new(point.dot(u), point.dot(v)) - chart.origin) * self.texel_density;
+        texel / self.settings.atlas_size as f32
+    }
+}
+
+/// Make one chart per group of connected coplanar polygons and pack them in one atlas.
+/// The layout only depends on the brush, so the same brush always gets the same lightmap.
+pub struct PackLightmap(pub LightmapSettings);
+
+impl BrushMeshOperation for PackLightmap {
+    type Out = LightmapAtlas;
+
+    fn apply(self, brush: &BrushMesh) -> Self::Out {
+        let settings = self.0;
+        let mut charts = chart_polygons(brush);
+        let charts_by_polygon = charts
+            .iter()
+            .enumerate()
+            .flat_map(|(i, chart)| chart.polygons.iter().map(move |polygon| (*polygon, i)))
+            .collect();
+
+        // The density is lowered until all the charts fit in the atlas.
+        let mut texel_density = settings.texel_density;
+        let mut attempts = 1;
+        while !pack_charts(&mut charts, settings, texel_density) {
+            if attempts == 32 {
+                warn!(
+                    "The lightmap charts don't fit in a {0}x{0} atlas.",
+                    settings.atlas_size
+                );
+                break;
+            }
+            attempts += 1;
+            texel_density *= 0.8;
+        }
+
+        LightmapAtlas {
+            settings,
+            texel_density,
+            charts,
+            charts_by_polygon,
+        }
+    }
+}
+
+/// Group the connected coplanar polygons of a brush, in the order of their first polygon.
+fn chart_polygons(brush: &BrushMesh) -> Vec<LightmapChart> {
+    let polygons: Vec<PolygonId> = brush
+        .polygons()
+        .filter(|poly| !brush.get_plane(poly.plane).holes().contains(&poly.id))
+        .map(|poly| poly.id)
+        .collect();
+    let coplanar = |a: PolygonId, b: PolygonId| {
+        let (a, b) = (brush.get_polygon(a).plane, brush.get_polygon(b).plane);
+        let (a, b) = (brush.get_plane(a), brush.get_plane(b));
+        a.normal
+            .normalize_or_zero()
+            .dot(b.normal.normalize_or_zero())
+            > 1.0 - BRUSH_EPSILON
+            && a.distance_to(b.point).abs() < BRUSH_EPSILON
+    };
+
+    // Each polygon points to a polygon of its group with a lower id, the first one to itself.
+    let mut groups: HashMap<PolygonId, PolygonId> = HashMap::new();
+    fn find(groups: &HashMap<PolygonId, PolygonId>, mut polygon: PolygonId) -> PolygonId {
+        while groups[&polygon] != polygon {
+            polygon = groups[&polygon];
+        }
+        polygon
+    }
+    for (i, polygon) in polygons.iter().enumerate() {
+        groups.insert(*polygon, *polygon);
+        let verticies = &brush.get_polygon(*polygon).verticies;
+        for other in &polygons[..i] {
+            let shares_vertice = brush
+                .get_polygon(*other)
+                .verticies
+                .iter()
+                .any(|vertice| verticies.contains(vertice));
+            if shares_vertice && coplanar(*polygon, *other) {
+                let (a, b) = (find(&groups, *polygon), find(&groups, *other));
+                groups.insert(a.max(b), a.min(b));
+            }
+        }
+    }
+
+    let mut charts: Vec<LightmapChart> = Vec::new();
+    let mut chart_of_group: HashMap<PolygonId, usize> = HashMap::new();
+    for polygon in polygons {
+        let group = find(&groups, polygon);
+        let chart = *chart_of_group.entry(group).or_insert_with(|| {
+            let normal = brush.get_plane(brush.get_polygon(polygon).plane).normal;
+            charts.push(LightmapChart {
+                polygons: Vec::new(),
+                axes: plane_axes(normal),
+                origin: Vec2::splat(f32::MAX),
+                extent: Vec2::ZERO,
+                size: UVec2::ZERO,
+                position: UVec2::ZERO,
+            });
+            charts.len() - 1
+        });
+        charts[chart].polygons.push(polygon);
+    }
+
+    for chart in &mut charts {
+        let (u, v) = chart.axes;
+        let (mut min, mut max) = (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN));
+        for polygon in &chart.polygons {
+            for vertice in &brush.get_polygon(*polygon).verticies {
+                let point = brush.get_vertice(*vertice).point;
+                let projected = Vec2::new(point.dot(u), point.dot(v));
+                min = min.min(projected);
+                max = max.max(projected);
+            }
+        }
+        chart.origin = min;
+        chart.extent = max - min;
+    }
+    charts
+}
+
+/// Place the charts in rows from the tallest to the smallest, returns whether they all fit.
+fn pack_charts(charts: &mut [LightmapChart], settings: LightmapSettings, density: f32) -> bool {
+    for chart in charts.iter_mut() {
+        let texels = (chart.extent * density).ceil().as_uvec2().max(UVec2::ONE);
+        chart.size = texels + UVec2::splat(settings.padding * 2);
+    }
+    let mut order: Vec<usize> = (0..charts.len()).collect();
+    order.sort_by(|a, b| {
+        let (a_size, b_size) = (charts[*a].size, charts[*b].size);
+        b_size
+            .y
+            .cmp(&a_size.y)
+            .then(b_size.x.cmp(&a_size.x))
+            .then(a.cmp(b))
+    });
+
+    let mut cursor = UVec2::ZERO;
+    let mut row_height = 0;
+    let mut fits = true;
+    for i in order {
+        let chart = &mut charts[i];
+        if cursor.x + chart.size.x > settings.atlas_size {
+            cursor = UVec2::new(0, cursor.y + row_height);
+            row_height = 0;
+        }
+        chart.position = cursor;
+        cursor.x += chart.size.x;
+        row_height = row_height.max(chart.size.y);
+        fits &= cursor.x <= settings.atlas_size && cursor.y + row_height <= settings.atlas_size;
+    }
+    fits
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::csg::Brushable;
+
+    fn overlap(a: &LightmapChart, b: &LightmapChart) -> bool {
+        let (a_max, b_max) = (a.position + a.size, b.position + b.size);
+        a.position.x < b_max.x
+            && b.position.x < a_max.x
+            && a.position.y < b_max.y
+            && b.position.y < a_max.y
+    }
+
+    #[test]
+    fn test_pack_cube() {
+        let cube = Cuboid::from_size(Vec3::splat(2.0)).to_default_brush();
+        let settings = LightmapSettings {
+            texel_density: 4.0,
+            atlas_size: 64,
+            padding: 1,
+        };
+        let atlas = PackLightmap(settings).apply(&cube);
+        assert_eq!(atlas, PackLightmap(settings).apply(&cube));
+        assert_eq!(atlas.texel_density, 4.0);
+        assert_eq!(atlas.charts.len(), 6);
+        for (i, chart) in atlas.charts.iter().enumerate() {
+            assert_eq!(chart.size, UVec2::splat(10));
+            for other in &atlas.charts[i + 1..] {
+                assert!(!overlap(chart, other));
+            }
+        }
+        for poly in cube.polygons() {
+            let chart = &atlas.charts[atlas.charts_by_polygon[&poly.id]];
+            let (min, max) = (
+                (chart.position + UVec2::ONE).as_vec2() / 64.0,
+                (chart.position + UVec2::splat(9)).as_vec2() / 64.0,
+            );
+            for vertice in poly.verticies() {
+                let uv = atlas.uv(poly.id, vertice.point);
+                assert!(uv.cmpge(min - 1e-5).all() && uv.cmple(max + 1e-5).all());
+            }
+        }
+
+        // The charts are shrunk to fit in a smaller atlas.
+        let small = LightmapSettings {
+            atlas_size: 16,
+            ..settings
+        };
+        let atlas = PackLightmap(small).apply(&cube);
+        assert!(atlas.texel_density < 4.0);
+        for chart in &atlas.charts {
+            assert!((chart.position + chart.size).cmple(UVec2::splat(16)).all());
+        }
+    }
+
+    #[test]
+    fn test_chart_groups() {
+        // Two quads sharing an edge and a third one apart, all on the same plane.
+        let mut brush = BrushMesh::empty();
+        let [a, b, c, d, e, f] = brush.add_vertices([
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+        ]);
+        let [g, h, i, j] = brush.add_vertices([
+            Vec3::new(5.0, 0.0, 0.0),
+            Vec3::new(5.0, 0.0, 1.0),
+            Vec3::new(6.0, 0.0, 1.0),
+            Vec3::new(6.0, 0.0, 0.0),
+        ]);
+        brush.add_polygon(&[a, b, c, d]);
+        brush.add_polygon(&[d, c, e, f]);
+        brush.add_polygon(&[g, h, i, j]);
+        let atlas = PackLightmap(LightmapSettings::default()).apply(&brush);
+        assert_eq!(atlas.charts.len(), 2);
+        assert_eq!(atlas.charts[0].polygons, vec![0, 1]);
+        assert_eq!(atlas.charts[1].polygons, vec![2]);
+    }
+}
@@ -1,7 +1,19 @@
 use super::BrushMesh;
 
-//mod clip;
-//pub use clip::*;
+mod boolean;
+pub use boolean::*;
+mod cleanup;
+pub use cleanup::*;
+mod clip;
+pub use clip::*;
+mod collider;
+pub use collider::*;
+mod hollow;
+pub use hollow::*;
+mod lightmap;
+pub use lightmap::*;
+mod transform;
+pub use transform::*;
 mod triangulate;
 pub use triangulate::*;
 
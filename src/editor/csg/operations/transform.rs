@@ -0,0 +1,108 @@
+use bevy::math::Affine3A;
+
+use crate::editor::csg::brush_mesh::{BrushMesh, Plane};
+use crate::prelude::*;
+
+use super::BrushMeshOperation;
+
+/// Move the vertices and planes of a brush with an affine transform.
+/// A mirroring transform flips the winding of the polygons so they keep facing outward.
+pub struct BakeTransform(pub Affine3A);
+
+impl BakeTransform {
+    /// Mirror across the plane going through the origin with the given normal.
+    pub fn mirror(normal: Direction3d) -> Self {
+        let normal = *normal;
+        let outer = Mat3::from_cols(normal * normal.x, normal * normal.y, normal * normal.z);
+        Self(Affine3A::from_mat3(Mat3::IDENTITY - 2.0 * outer))
+    }
+}
+
+impl From<Transform> for BakeTransform {
+    fn from(transform: Transform) -> Self {
+        Self(transform.compute_affine())
+    }
+}
+
+impl From<GlobalTransform> for BakeTransform {
+    fn from(transform: GlobalTransform) -> Self {
+        Self(transform.affine())
+    }
+}
+
+impl BrushMeshOperation for BakeTransform {
+    type Out = BrushMesh;
+
+    fn apply(self, brush: &BrushMesh) -> Self::Out {
+        let BakeTransform(affine) = self;
+        if affine == Affine3A::IDENTITY {
+            return brush.clone();
+        }
+        let linear = Mat3::from(affine.matrix3);
+        let mirrored = linear.determinant() < 0.0;
+        // Normals are moved by the inverse transpose to stay orthogonal to their plane.
+        let normal_matrix = linear.inverse().transpose();
+
+        let mut result = BrushMesh::empty();
+        result.extend_verticies(
+            brush
+                .vertices()
+                .map(|vertice| affine.transform_point3(vertice.point)),
+        );
+        let planes = result.extend_planes(brush.planes().map(|plane| {
+            Plane::new(
+                affine.transform_point3(plane.point),
+                (normal_matrix * plane.normal).normalize_or_zero(),
+            )
+        }));
+        for poly in brush.polygons() {
+            let mut vertices = poly.verticies.clone();
+            if mirrored {
+                vertices.reverse();
+            }
+            let polygon = result.add_polygon_on_plane(&vertices, planes[poly.plane]);
+            result.set_polygon_surface(polygon, poly.surface);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::csg::{convert::Slope, Brushable};
+
+    #[test]
+    fn test_bake_transform() {
+        let slope: BrushMesh = Slope {
+            length: 2.0,
+            width: 1.0,
+            height: 1.0,
+        }
+        .into();
+        let transform = Transform::from_xyz(1.0, 2.0, 3.0)
+            .with_rotation(Quat::from_rotation_y(0.3))
+            .with_scale(Vec3::new(1.0, 2.0, 0.5));
+        let baked = BakeTransform::from(transform).apply(&slope);
+        assert_eq!(baked.validate(), Ok(()));
+        assert!((baked.volume() - slope.volume()).abs() < 1e-4);
+        assert!(
+            baked
+                .center()
+                .distance(transform.transform_point(slope.center()))
+                < 1e-4
+        );
+    }
+
+    #[test]
+    fn test_mirror() {
+        let cube = Cuboid::new(1.0, 2.0, 3.0).to_default_brush();
+        let mirrored = BakeTransform::mirror(Direction3d::X).apply(&cube);
+        assert_eq!(mirrored.validate(), Ok(()));
+        assert!((mirrored.volume() - 6.0).abs() < 1e-4);
+
+        let flipped =
+            BakeTransform::from(Transform::from_scale(Vec3::new(1.0, -1.0, 1.0))).apply(&cube);
+        assert_eq!(flipped.validate(), Ok(()));
+    }
+}
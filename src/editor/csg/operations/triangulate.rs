@@ -1,56 +1,743 @@
 use bevy::render::{
     mesh::Indices, render_asset::RenderAssetUsages, render_resource::PrimitiveTopology,
 };
+use bevy::utils::HashMap;
 
 use super::BrushMesh;
-use super::BrushMeshOperation;
+use super::{BrushMeshOperation, LightmapAtlas, LightmapSettings, PackLightmap};
+use crate::editor::csg::brush_mesh::{
+    BrushMaterial, BrushPolygon, PolygonId, VerticeId, BRUSH_EPSILON,
+};
 use crate::prelude::*;
 
-pub struct Triangulate;
+/// Which polygons share their normals at their common verticies.
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum Smoothing {
+    /// Every polygon keeps the normal of its plane.
+    #[default]
+    Flat,
+    /// Polygons with the same smoothing group in their surface are smoothed together.
+    Groups,
+    /// Polygons whose normals are at most this angle apart, in radians, are smoothed together.
+    Angle(f32),
+}
+
+impl Smoothing {
+    fn joins(
+        &self,
+        (group, normal): (Option<u32>, Vec3),
+        (other_group, other_normal): (Option<u32>, Vec3),
+    ) -> bool {
+        match self {
+            Self::Flat => false,
+            Self::Groups => group.is_some() && group == other_group,
+            Self::Angle(angle) => normal.dot(other_normal) >= angle.cos() - BRUSH_EPSILON,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Triangulate {
+    pub smoothing: Smoothing,
+    /// Lay the polygons out in a lightmap atlas, in the second uv channel.
+    pub lightmap: Option<LightmapSettings>,
+}
 
 impl BrushMeshOperation for Triangulate {
     type Out = Mesh;
 
+    /// Each polygon gets its own verticies with the uvs and tangents of its texture projection.
+    /// Their normals are the ones of its plane unless it is smoothed with its neighbours.
+    fn apply(self, brush: &BrushMesh) -> Self::Out {
+        let lightmap = self
+            .lightmap
+            .map(|settings| PackLightmap(settings).apply(brush));
+        triangulate(brush, self.smoothing, lightmap.as_ref(), |_| true)
+    }
+}
+
+/// Triangulate a brush in one mesh per material, in the order the materials first appear.
+/// The normals are smoothed across the materials.
+/// All the meshes share the same lightmap atlas.
+#[derive(Default)]
+pub struct TriangulateMaterials {
+    pub smoothing: Smoothing,
+    pub lightmap: Option<LightmapSettings>,
+}
+
+impl BrushMeshOperation for TriangulateMaterials {
+    type Out = Vec<(Option<BrushMaterial>, Mesh)>;
+
     fn apply(self, brush: &BrushMesh) -> Self::Out {
-        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
-        let mut indicies = Vec::new();
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, brush.positions());
-
-        for poly in brush.polygons() {
-            let mut verts = poly.verticies().collect::<Vec<_>>();
-            while verts.len() > 3 {
-                let len = verts.len();
-                'outer: for i in 0..len {
-                    let v1 = verts[i].data.point;
-                    let v2 = verts[(i + 1) % len].data.point;
-                    let v3 = verts[(i + 2) % len].data.point;
-                    // Check if its convex.
-                    let angle = (v1 - v2).angle_between(v3 - v2);
-                    if angle > std::f32::consts::PI {
-                        continue;
-                    }
-                    // Check if no other point is in the triangle.
-                    for j in 0..len - 3 {
-                        let idx = (i + 3 + j) % len;
-                        let point = verts[idx].point;
-                        if point.in_triangle(v1, v2, v3) {
-                            dbg!("Skip Ear");
-                            continue 'outer;
-                        }
-                    }
-                    indicies.extend(&[
-                        verts[i].id as u32,
-                        verts[(i + 1) % len].id as u32,
-                        verts[(i + 2) % len].id as u32,
-                    ]);
-                    verts.remove((i + 1) % len);
-                    break;
+        let mut materials: Vec<Option<BrushMaterial>> = Vec::new();
+        for poly in brush.polygons().filter(|poly| !is_hole(poly)) {
+            if !materials.contains(&poly.surface.material) {
+                materials.push(poly.surface.material);
+            }
+        }
+        let lightmap = self
+            .lightmap
+            .map(|settings| PackLightmap(settings).apply(brush));
+        materials
+            .into_iter()
+            .map(|material| {
+                let mesh = triangulate(brush, self.smoothing, lightmap.as_ref(), |poly| {
+                    poly.surface.material == material
+                });
+                (material, mesh)
+            })
+            .collect()
+    }
+}
+
+/// Whether a polygon is a hole in the polygons of its plane.
+fn is_hole(poly: &BrushPolygon) -> bool {
+    poly.brush.get_plane(poly.plane).holes().contains(&poly.id)
+}
+
+fn triangulate(
+    brush: &BrushMesh,
+    smoothing: Smoothing,
+    lightmap: Option<&LightmapAtlas>,
+    filter: impl Fn(&BrushPolygon) -> bool,
+) -> Mesh {
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
+    let mut indicies = Vec::new();
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut tangents = Vec::new();
+    let mut lightmap_uvs = Vec::new();
+
+    let face = |polygon: PolygonId| {
+        let polygon = brush.get_polygon(polygon);
+        let normal = brush.get_plane(polygon.plane).normal.normalize_or_zero();
+        (polygon.surface.smoothing_group, normal)
+    };
+    // The polygons around each vertice.
+    let mut corners: HashMap<VerticeId, Vec<PolygonId>> = HashMap::new();
+    if smoothing != Smoothing::Flat {
+        for poly in brush.polygons().filter(|poly| !is_hole(poly)) {
+            for vertice in &poly.data.verticies {
+                corners.entry(*vertice).or_default().push(poly.id);
+            }
+        }
+    }
+
+    for poly in brush.polygons().filter(|poly| filter(poly)) {
+        let (group, normal) = face(poly.id);
+        let texture = poly.surface.texture;
+        let tangent = texture.tangent(normal);
+        let start = positions.len() as u32;
+        let (verticies, triangles) = polygon_triangles(brush, poly.id);
+        for vertice in verticies {
+            let point = brush.get_vertice(vertice).point;
+            // Each direction is summed once so a face split in several polygons doesn't weigh
+            // more than the others.
+            let mut joined = vec![normal];
+            for (_, other) in corners
+                .get(&vertice)
+                .into_iter()
+                .flatten()
+                .map(|other| face(*other))
+                .filter(|other| smoothing.joins((group, normal), *other))
+            {
+                if joined.iter().all(|n| n.dot(other) < 1.0 - BRUSH_EPSILON) {
+                    joined.push(other);
                 }
             }
-            indicies.extend(&[verts[0].id as u32, verts[1].id as u32, verts[2].id as u32]);
+            let smoothed: Vec3 = joined.into_iter().sum();
+            positions.push(point);
+            normals.push(smoothed.try_normalize().unwrap_or(normal));
+            uvs.push(texture.uv(point, normal));
+            tangents.push(tangent);
+            if let Some(lightmap) = lightmap {
+                lightmap_uvs.push(lightmap.uv(poly.id, point));
+            }
+        }
+        indicies.extend(
+            triangles
+                .iter()
+                .flatten()
+                .map(|corner| start + *corner as u32),
+        );
+    }
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_TANGENT, tangents);
+    if lightmap.is_some() {
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_1, lightmap_uvs);
+    }
+    mesh.insert_indices(Indices::U32(indicies));
+    mesh
+}
+
+/// The verticies of a polygon followed by the ones of the holes of its plane inside it, with the
+/// triangles covering the polygon as indices in these verticies.
+/// A hole is not covered by itself.
+pub(crate) fn polygon_triangles(
+    brush: &BrushMesh,
+    polygon: PolygonId,
+) -> (Vec<VerticeId>, Vec<[usize; 3]>) {
+    let data = brush.get_polygon(polygon);
+    let plane = brush.get_plane(data.plane);
+    if plane.holes().contains(&polygon) {
+        return (Vec::new(), Vec::new());
+    }
+    let points = |verticies: &[VerticeId]| -> Vec<Vec3> {
+        verticies
+            .iter()
+            .map(|vertice| brush.get_vertice(*vertice).point)
+            .collect()
+    };
+    let mut verticies = data.verticies.clone();
+    let mut loops = vec![points(&data.verticies)];
+    let (u, v) = plane_axes(plane.normal);
+    let outline: Vec<Vec2> = loops[0].iter().map(|point| project(*point, u, v)).collect();
+    for hole in plane.holes() {
+        let hole = &brush.get_polygon(*hole).verticies;
+        let hole_points = points(hole);
+        if contains(&outline, project(hole_points[0], u, v)) {
+            verticies.extend(hole);
+            loops.push(hole_points);
+        }
+    }
+    (verticies, triangulate_loops(&loops, plane.normal))
+}
+
+/// Split the polygon made of `loops` in triangles, given as indices in the points of all the
+/// loops one after the other.
+/// The first loop is the outline of the polygon and the others are its holes. The triangles go
+/// counterclockwise around `normal`, or the normal of the outline when it is null.
+/// Concave outlines, collinear or repeated points and loops going either way are handled,
+/// the points left after the last triangle only make up a null area.
+pub(crate) fn triangulate_loops(loops: &[Vec<Vec3>], normal: Vec3) -> Vec<[usize; 3]> {
+    let Some(outline) = loops.first() else {
+        return Vec::new();
+    };
+    let Some(normal) = normal
+        .try_normalize()
+        .or_else(|| newell_normal(outline).try_normalize())
+    else {
+        return Vec::new();
+    };
+    let (u, v) = plane_axes(normal);
+    let points: Vec<Vec2> = loops
+        .iter()
+        .flatten()
+        .map(|point| project(*point, u, v))
+        .collect();
+
+    // The outline goes counterclockwise and the holes clockwise.
+    let mut start = 0;
+    let mut rings: Vec<Vec<usize>> = loops
+        .iter()
+        .enumerate()
+        .map(|(i, points_loop)| {
+            let mut ring: Vec<usize> = (start..start + points_loop.len()).collect();
+            start += points_loop.len();
+            if (signed_area(&points, &ring) < 0.0) == (i == 0) {
+                ring.reverse();
+            }
+            ring
+        })
+        .collect();
+    let mut ring = rings.remove(0);
+
+    // The holes are bridged to the outline from right to left, so each one can see it.
+    let right = |ring: &Vec<usize>| {
+        ring.iter()
+            .map(|point| points[*point].x)
+            .fold(f32::MIN, f32::max)
+    };
+    rings.sort_by(|a, b| right(b).total_cmp(&right(a)));
+    for hole in rings.iter().filter(|hole| hole.len() >= 3) {
+        bridge_hole(&points, &mut ring, hole);
+    }
+    clip_ears(&points, ring)
+}
+
+/// Two axes making a direct base with `normal`.
+pub(crate) fn plane_axes(normal: Vec3) -> (Vec3, Vec3) {
+    let normal = normal.normalize_or_zero();
+    let u = normal.any_orthonormal_vector();
+    (u, normal.cross(u))
+}
+
+fn project(point: Vec3, u: Vec3, v: Vec3) -> Vec2 {
+    Vec2::new(point.dot(u), point.dot(v))
+}
+
+fn newell_normal(points: &[Vec3]) -> Vec3 {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| (*a - *b).cross(*a + *b))
+        .sum::<Vec3>()
+}
+
+/// Twice the area of the triangle `abc`, positive when it is counterclockwise.
+fn cross(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+    (b - a).perp_dot(c - a)
+}
+
+fn signed_area(points: &[Vec2], ring: &[usize]) -> f32 {
+    ring.iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|(a, b)| points[*a].perp_dot(points[*b]))
+        .sum::<f32>()
+        * 0.5
+}
+
+/// Whether `point` is inside a polygon, using the even-odd rule.
+fn contains(polygon: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+    for (a, b) in polygon.iter().zip(polygon.iter().cycle().skip(1)) {
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Whether `point` is inside or on the border of the triangle `abc`, going either way.
+fn in_triangle(point: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    let (ab, bc, ca) = (cross(a, b, point), cross(b, c, point), cross(c, a, point));
+    (ab >= 0.0 && bc >= 0.0 && ca >= 0.0) || (ab <= 0.0 && bc <= 0.0 && ca <= 0.0)
+}
+
+/// The sine of the turn of the ring at `i`, positive when the corner is convex.
+fn turn(points: &[Vec2], ring: &[usize], i: usize) -> f32 {
+    let len = ring.len();
+    let a = points[ring[(i + len - 1) % len]];
+    let b = points[ring[i]];
+    let c = points[ring[(i + 1) % len]];
+    let lengths = (b - a).length() * (c - b).length();
+    if lengths <= f32::EPSILON {
+        return 0.0;
+    }
+    cross(a, b, c) / lengths
+}
+
+/// Whether `point` is seen from the point of the ring at `i` between its edges.
+fn locally_inside(points: &[Vec2], ring: &[usize], i: usize, point: Vec2) -> bool {
+    let len = ring.len();
+    let prev = points[ring[(i + len - 1) % len]];
+    let current = points[ring[i]];
+    let next = points[ring[(i + 1) % len]];
+    let (after_prev, before_next) = (cross(prev, current, point), cross(current, next, point));
+    if cross(prev, current, next) >= 0.0 {
+        after_prev >= 0.0 && before_next >= 0.0
+    } else {
+        after_prev >= 0.0 || before_next >= 0.0
+    }
+}
+
+/// Join a clockwise hole to a counterclockwise ring, through a bridge going from the rightmost
+/// point of the hole to a point of the ring it sees, and back.
+/// A hole outside of the ring is left out.
+fn bridge_hole(points: &[Vec2], ring: &mut Vec<usize>, hole: &[usize]) {
+    let Some((start, origin)) = hole
+        .iter()
+        .map(|point| points[*point])
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.x.total_cmp(&b.x))
+    else {
+        return;
+    };
+
+    // The closest edge of the ring to the right of the hole.
+    let len = ring.len();
+    let mut hit: Option<(f32, usize)> = None;
+    for i in 0..len {
+        let (a, b) = (points[ring[i]], points[ring[(i + 1) % len]]);
+        if (a.y > origin.y) == (b.y > origin.y) {
+            continue;
+        }
+        let x = a.x + (origin.y - a.y) / (b.y - a.y) * (b.x - a.x);
+        if x < origin.x || hit.is_some_and(|(closest, _)| closest <= x) {
+            continue;
+        }
+        let hit_point = Vec2::new(x, origin.y);
+        // The ray can go right through a point of the ring, which is then seen.
+        let end = if a.distance(hit_point) <= BRUSH_EPSILON {
+            i
+        } else if b.distance(hit_point) <= BRUSH_EPSILON || b.x > a.x {
+            (i + 1) % len
+        } else {
+            i
+        };
+        hit = Some((x, end));
+    }
+    let Some((x, mut target)) = hit else {
+        return;
+    };
+
+    // The right end of the edge can be hidden by reflex points of the ring, the one closest to
+    // the ray is seen.
+    let hit_point = Vec2::new(x, origin.y);
+    let end = points[ring[target]];
+    let mut best = if end.distance(hit_point) <= BRUSH_EPSILON {
+        0.0
+    } else {
+        f32::MAX
+    };
+    for i in 0..len {
+        let point = points[ring[i]];
+        let offset = point - origin;
+        if i == target
+            || offset.x <= 0.0
+            || turn(points, ring, i) >= 0.0
+            || !in_triangle(point, origin, hit_point, end)
+        {
+            continue;
+        }
+        let slope = offset.y.abs() / offset.x;
+        if slope < best && locally_inside(points, ring, i, origin) {
+            best = slope;
+            target = i;
         }
-        mesh.insert_indices(Indices::U32(indicies));
+    }
+    // A point can appear several times in the ring, the bridge has to start between the edges
+    // going around the hole.
+    let end = points[ring[target]];
+    if !locally_inside(points, ring, target, origin) {
+        if let Some(other) = (0..len).find(|i| {
+            points[ring[*i]].distance(end) <= BRUSH_EPSILON
+                && locally_inside(points, ring, *i, origin)
+        }) {
+            target = other;
+        }
+    }
+
+    let mut bridged = Vec::with_capacity(len + hole.len() + 2);
+    bridged.extend_from_slice(&ring[..=target]);
+    bridged.extend(hole[start..].iter().chain(&hole[..=start]));
+    bridged.push(ring[target]);
+    bridged.extend_from_slice(&ring[target + 1..]);
+    *ring = bridged;
+}
+
+/// Whether the corner of the ring at `i` is a convex triangle without any other point in it.
+fn is_ear(points: &[Vec2], ring: &[usize], i: usize) -> bool {
+    if turn(points, ring, i) <= BRUSH_EPSILON {
+        return false;
+    }
+    let len = ring.len();
+    let corner = [(i + len - 1) % len, i, (i + 1) % len];
+    let [a, b, c] = corner.map(|j| points[ring[j]]);
+    let triangle = [a, b, c];
+    (0..len).filter(|j| !corner.contains(j)).all(|j| {
+        let point = points[ring[j]];
+        // The ends of the bridges appear twice in the ring, the edges of the other copy must stay
+        // out of the ear.
+        if let Some(k) = triangle
+            .iter()
+            .position(|end| end.distance_squared(point) <= BRUSH_EPSILON * BRUSH_EPSILON)
+        {
+            let (next, prev) = (triangle[(k + 1) % 3], triangle[(k + 2) % 3]);
+            return [ring[(j + len - 1) % len], ring[(j + 1) % len]]
+                .iter()
+                .all(|other| {
+                    let other = points[*other];
+                    cross(point, next, other) <= 0.0 || cross(prev, point, other) <= 0.0
+                });
+        }
+        // Only the points of the ring which aren't convex can be in one of its ears.
+        turn(points, ring, j) > 0.0 || !in_triangle(point, a, b, c)
+    })
+}
+
+/// Cut the ears of a counterclockwise ring until there is no area left.
+fn clip_ears(points: &[Vec2], mut ring: Vec<usize>) -> Vec<[usize; 3]> {
+    let mut triangles = Vec::with_capacity(ring.len().saturating_sub(2));
+    while ring.len() >= 3 {
+        let len = ring.len();
+        let ear = (0..len).find(|i| is_ear(points, &ring, *i)).or_else(|| {
+            // Rounding errors can hide every ear, the most convex corner is cut instead.
+            (0..len)
+                .map(|i| (i, turn(points, &ring, i)))
+                .filter(|(_, turn)| *turn > 0.0)
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(i, _)| i)
+        });
+        // Only collinear or reflex corners are left.
+        let Some(i) = ear else {
+            break;
+        };
+        triangles.push([ring[(i + len - 1) % len], ring[i], ring[(i + 1) % len]]);
+        ring.remove(i);
+    }
+    triangles
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::csg::{
+        brush_mesh::{BrushMaterial, Surface, TextureColor, TextureProjection},
+        convert::RoundBrushSettings,
+        Brushable,
+    };
+    use bevy::render::mesh::VertexAttributeValues;
+
+    #[test]
+    fn test_triangulate_uvs() {
+        let mut cube = Cuboid::from_size(Vec3::splat(2.0)).to_default_brush();
+        let surface = Surface {
+            texture: TextureProjection {
+                scale: Vec2::splat(4.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for polygon in 0..cube.polygons().count() {
+            cube.set_polygon_surface(polygon, surface);
+        }
+        let mesh = Triangulate::default().apply(&cube);
+        assert_eq!(mesh.count_vertices(), 24);
+        assert_eq!(mesh.indices().unwrap().len(), 36);
+        let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0)
+        else {
+            panic!("The mesh has no uvs.");
+        };
+        // The corners of the cube are a quarter of the texture apart.
+        assert!(uvs
+            .iter()
+            .all(|uv| uv.iter().all(|coord| (coord.abs() - 0.25).abs() < 1e-5)));
+        assert!(mesh.attribute(Mesh::ATTRIBUTE_TANGENT).is_some());
+    }
+
+    #[test]
+    fn test_triangulate_lightmap() {
+        let cube = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        assert!(Triangulate::default()
+            .apply(&cube)
+            .attribute(Mesh::ATTRIBUTE_UV_1)
+            .is_none());
+        let meshes = TriangulateMaterials {
+            lightmap: Some(LightmapSettings::default()),
+            ..Default::default()
+        }
+        .apply(&cube);
+        let Some(VertexAttributeValues::Float32x2(uvs)) =
+            meshes[0].1.attribute(Mesh::ATTRIBUTE_UV_1)
+        else {
+            panic!("The mesh has no lightmap uvs.");
+        };
+        assert_eq!(uvs.len(), 24);
+        assert!(uvs
+            .iter()
+            .all(|uv| uv.iter().all(|coord| (0.0..=1.0).contains(coord))));
+    }
+
+    #[test]
+    fn test_triangulate_materials() {
+        let mut cube = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        let red = Some(BrushMaterial::new(TextureColor::Red, 1));
+        for polygon in [1, 3] {
+            cube.set_polygon_surface(
+                polygon,
+                Surface {
+                    material: red,
+                    ..Default::default()
+                },
+            );
+        }
+        let meshes = TriangulateMaterials::default().apply(&cube);
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].0, None);
+        assert_eq!(meshes[0].1.indices().unwrap().len(), 4 * 6);
+        assert_eq!(meshes[1].0, red);
+        assert_eq!(meshes[1].1.indices().unwrap().len(), 2 * 6);
+    }
+
+    fn normals(mesh: &Mesh) -> Vec<Vec3> {
+        let Some(VertexAttributeValues::Float32x3(normals)) =
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+        else {
+            panic!("The mesh has no normals.");
+        };
+        normals.iter().map(|normal| Vec3::from(*normal)).collect()
+    }
+
+    #[test]
+    fn test_smoothing() {
+        let cube = Cuboid::from_size(Vec3::ONE).to_default_brush();
+        let flat = normals(&Triangulate::default().apply(&cube));
+        assert!(flat.iter().all(|normal| normal.abs().max_element() == 1.0));
+        // The edges of the cube are hard under the threshold and smooth above it.
+        let sharp = Triangulate {
+            smoothing: Smoothing::Angle(FRAC_PI_4),
+            ..Default::default()
+        };
+        assert_eq!(normals(&sharp.apply(&cube)), flat);
+        let smooth = Triangulate {
+            smoothing: Smoothing::Angle(FRAC_PI_2),
+            ..Default::default()
+        };
+        let corner = Vec3::ONE.normalize();
+        assert!(normals(&smooth.apply(&cube))
+            .iter()
+            .all(|normal| (normal.abs() - corner).length() < 1e-5));
+
+        // A face split in two polygons counts once at the corners they share.
+        let mut split = BrushMesh::empty();
+        let [a, b, c, d, e, f, g, h] = split.add_vertices([
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(0.0, 1.0, 1.0),
+        ]);
+        split.add_polygon(&[a, b, c]);
+        split.add_polygon(&[a, c, d]);
+        split.add_polygon(&[e, h, g, f]);
+        split.add_polygon(&[a, d, h, e]);
+        split.add_polygon(&[b, f, g, c]);
+        split.add_polygon(&[a, e, f, b]);
+        split.add_polygon(&[d, c, g, h]);
+        let smooth = Triangulate {
+            smoothing: Smoothing::Angle(FRAC_PI_2),
+            ..Default::default()
+        };
+        assert!(normals(&smooth.apply(&split))
+            .iter()
+            .all(|normal| (normal.abs() - corner).length() < 1e-5));
+
+        // Only the sides of a cylinder are in a smoothing group.
+        let cylinder = Cylinder::new(1.0, 2.0).to_brush(RoundBrushSettings {
+            segments: 4,
+            ..Default::default()
+        });
+        let groups = Triangulate {
+            smoothing: Smoothing::Groups,
+            ..Default::default()
+        };
+        // The verticies of the square prism are on the axes, between two diagonal sides.
+        assert!(normals(&groups.apply(&cylinder))
+            .iter()
+            .all(|normal| normal.abs().max_element() > 1.0 - 1e-5));
+    }
+
+    /// The area covered by triangles, checking they all go counterclockwise around the normal.
+    fn covered_area(loops: &[Vec<Vec3>], triangles: &[[usize; 3]], normal: Vec3) -> f32 {
+        let points: Vec<Vec3> = loops.iter().flatten().copied().collect();
+        triangles
+            .iter()
+            .map(|[a, b, c]| {
+                let area = (points[*b] - points[*a]).cross(points[*c] - points[*a]);
+                assert!(
+                    area.dot(normal) > 0.0,
+                    "The triangle {a} {b} {c} is flipped."
+                );
+                area.length() * 0.5
+            })
+            .sum()
+    }
+
+    fn square(size: f32, center: Vec3) -> Vec<Vec3> {
+        [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .map(|(x, z)| center + Vec3::new(x, 0.0, z) * size * 0.5)
+            .to_vec()
+    }
+
+    #[test]
+    fn test_concave_polygon() {
+        // An L going either way around the normal.
+        let mut outline = [
+            (0.0, 0.0),
+            (0.0, 2.0),
+            (1.0, 2.0),
+            (1.0, 1.0),
+            (2.0, 1.0),
+            (2.0, 0.0),
+        ]
+        .map(|(x, z)| Vec3::new(x, 0.0, z))
+        .to_vec();
+        for _ in 0..2 {
+            let loops = vec![outline.clone()];
+            let triangles = triangulate_loops(&loops, Vec3::Y);
+            assert_eq!(triangles.len(), 4);
+            assert!((covered_area(&loops, &triangles, Vec3::Y) - 3.0).abs() < 1e-5);
+            outline.reverse();
+        }
+        // The normal of the outline is used without one.
+        let loops = vec![outline];
+        let triangles = triangulate_loops(&loops, Vec3::ZERO);
+        assert!((covered_area(&loops, &triangles, Vec3::Y) - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_degenerate_polygons() {
+        // The collinear points are kept so the neighbouring polygons have no cracks.
+        let outline: Vec<Vec3> = (0..8)
+            .map(|i| {
+                let corner = square(2.0, Vec3::ZERO)[i / 2];
+                let next = square(2.0, Vec3::ZERO)[(i / 2 + 1) % 4];
+                if i % 2 == 0 {
+                    corner
+                } else {
+                    (corner + next) * 0.5
+                }
+            })
+            .collect();
+        let loops = vec![outline];
+        let triangles = triangulate_loops(&loops, Vec3::Y);
+        assert_eq!(triangles.len(), 6);
+        assert!((covered_area(&loops, &triangles, Vec3::Y) - 4.0).abs() < 1e-5);
+        for point in 0..8 {
+            assert!(triangles.iter().flatten().any(|corner| *corner == point));
+        }
+
+        // Repeated points.
+        let mut outline = square(1.0, Vec3::ZERO);
+        outline.insert(2, outline[1]);
+        let loops = vec![outline];
+        let triangles = triangulate_loops(&loops, Vec3::Y);
+        assert!((covered_area(&loops, &triangles, Vec3::Y) - 1.0).abs() < 1e-5);
+
+        // Nothing to cover.
+        let line = vec![vec![Vec3::ZERO, Vec3::X, Vec3::X * 2.0, Vec3::X * 3.0]];
+        assert!(triangulate_loops(&line, Vec3::Y).is_empty());
+        assert!(triangulate_loops(&line, Vec3::ZERO).is_empty());
+        assert!(triangulate_loops(&[], Vec3::Y).is_empty());
+    }
+
+    #[test]
+    fn test_holes() {
+        let mut hole = square(1.0, Vec3::ZERO);
+        hole.reverse();
+        let loops = vec![
+            square(4.0, Vec3::ZERO),
+            hole,
+            square(0.5, Vec3::new(1.5, 0.0, 1.5)),
+            // Lined up with the first hole.
+            square(0.5, Vec3::new(-1.25, 0.0, 0.25)),
+            // Outside of the outline.
+            square(1.0, Vec3::X * 10.0),
+        ];
+        let triangles = triangulate_loops(&loops, Vec3::Y);
+        let area = 16.0 - 1.0 - 0.25 - 0.25;
+        assert!((covered_area(&loops, &triangles, Vec3::Y) - area).abs() < 1e-4);
+        assert!(triangles.iter().flatten().all(|corner| *corner < 16));
 
-        mesh.with_duplicated_vertices().with_computed_flat_normals()
+        // The holes of a plane are left uncovered.
+        let mut brush = BrushMesh::empty();
+        let outline = brush.extend_verticies(square(4.0, Vec3::ZERO));
+        let hole = brush.extend_verticies(square(2.0, Vec3::ZERO));
+        let plane = brush.add_plane(Vec3::Y, Vec3::ZERO);
+        let reversed: Vec<VerticeId> = outline.iter().rev().copied().collect();
+        brush.add_polygon_on_plane(&reversed, plane);
+        brush.add_hole_on_plane(&hole, plane);
+        let mesh = Triangulate::default().apply(&brush);
+        assert_eq!(mesh.count_vertices(), 8);
+        assert_eq!(mesh.indices().unwrap().len(), 8 * 3);
     }
 }
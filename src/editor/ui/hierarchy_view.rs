@@ -1,6 +1,12 @@
+use std::marker::PhantomData;
+
+use bevy::reflect::Struct;
+
 use crate::prelude::*;
 
-use super::super::csg::{hierarchy::CsgHierarchyEvent, CsgRoot, CsgLeaf, CsgNode};
+use super::super::csg::{
+    convert::BrushGroup, hierarchy::CsgHierarchyEvent, CsgLeaf, CsgNode, CsgOp, CsgRoot,
+};
 
 #[derive(Component, Debug, Default, Reflect)]
 #[reflect(Component)]
@@ -14,6 +20,19 @@ pub struct HierarchyViewContainer;
 #[derive(Component)]
 pub struct CsgRefNode(Entity);
 
+/// Button cycling the [`CsgOp`] of the csg node it points to.
+#[derive(Component)]
+pub struct CsgOpButton(Entity);
+
+/// Button stepping a field of the [`BrushGroup`] `G` of the csg node it points to.
+#[derive(Component)]
+pub struct BrushGroupFieldButton<G: BrushGroup> {
+    node: Entity,
+    field: String,
+    step: f32,
+    group: PhantomData<G>,
+}
+
 #[derive(Event)]
 pub struct RefreshHierarchyView;
 
@@ -43,41 +62,167 @@ pub fn on_h_ui_refresh(
     mut e_refresh: EventReader<RefreshHierarchyView>,
     h_ui_cont: Query<Entity, With<HierarchyViewContainer>>,
     csg_root: Query<&Children, With<CsgRoot>>,
-    csg_nodes: Query<(Entity, &Name, &Children), With<CsgNode>>,
-    csg_leafs: Query<(Entity, &Name), With<CsgLeaf>>,
+    csg_nodes: Query<(Entity, &Name, &Children, Option<&CsgOp>), With<CsgNode>>,
+    csg_leafs: Query<(Entity, &Name, Option<&CsgOp>), With<CsgLeaf>>,
 ) {
-    for _ in e_refresh.read() {
-        let mut ui_parent = commands.ui_builder(h_ui_cont.single());
-        let csg_children = csg_root.single();
+    if e_refresh.read().count() == 0 {
+        return;
+    }
+    let container = h_ui_cont.single();
+    commands.entity(container).despawn_descendants();
+    let mut ui_parent = commands.ui_builder(container);
+    for csg_children in &csg_root {
         spawn_ui_nodes_rec(&mut ui_parent, csg_children, &csg_nodes, &csg_leafs);
     }
 }
 
+/// Rebuild the tree once the brushes of a changed group are regenerated.
+pub fn refresh_on_brush_group_changed<G: BrushGroup>(
+    groups: Query<(), Changed<G>>,
+    mut e_refresh: EventWriter<RefreshHierarchyView>,
+) {
+    if !groups.is_empty() {
+        e_refresh.send(RefreshHierarchyView);
+    }
+}
+
+/// Add a row with buttons stepping each field of the new ui nodes of the groups `G`.
+pub fn add_brush_group_fields<G: BrushGroup>(
+    mut commands: Commands,
+    ui_nodes: Query<(Entity, &CsgRefNode), Added<CsgRefNode>>,
+    groups: Query<&G>,
+) {
+    const STEP: f32 = 0.25;
+    for (ui_node, CsgRefNode(csg_node)) in &ui_nodes {
+        let Ok(group) = groups.get(*csg_node) else {
+            continue;
+        };
+        commands.ui_builder(ui_node).column(|col| {
+            for (i, value) in group.iter_fields().enumerate() {
+                let field = group.name_at(i).unwrap_or_default().to_string();
+                let button = |step: f32| BrushGroupFieldButton::<G> {
+                    node: *csg_node,
+                    field: field.clone(),
+                    step,
+                    group: PhantomData,
+                };
+                col.row(|row| {
+                    row.button("-").insert(button(-STEP));
+                    row.label(LabelConfig {
+                        label: format!("{field}: {}", field_text(value)),
+                        ..default()
+                    });
+                    row.button("+").insert(button(STEP));
+                });
+            }
+        });
+    }
+}
+
+/// Step the field of a brush group when its button is pressed, the group is then regenerated.
+pub fn step_brush_group_fields<G: BrushGroup>(
+    buttons: Query<(&Interaction, &BrushGroupFieldButton<G>), Changed<Interaction>>,
+    mut groups: Query<&mut G>,
+) {
+    for (interaction, button) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Ok(mut group) = groups.get_mut(button.node) else {
+            error!("The csg node of a brush group button doesn't have its group.");
+            continue;
+        };
+        let Some(value) = group.field_mut(&button.field) else {
+            continue;
+        };
+        if let Some(value) = value.downcast_mut::<f32>() {
+            // The sizes stay positive so the brushes don't turn inside out.
+            let stepped = *value + button.step;
+            if stepped > 0.0 || G::SIGNED_FIELDS.contains(&button.field.as_str()) {
+                *value = stepped;
+            }
+        } else if let Some(value) = value.downcast_mut::<usize>() {
+            *value = value.saturating_add_signed(button.step.signum() as isize);
+        } else if let Some(value) = value.downcast_mut::<Option<f32>>() {
+            // Stepping below zero turns the field off.
+            *value = Some(value.unwrap_or(0.0) + button.step).filter(|value| *value > 0.0);
+        } else {
+            error!(
+                "The field {} of a brush group isn't a number.",
+                button.field
+            );
+        }
+    }
+}
+
+fn field_text(value: &dyn Reflect) -> String {
+    if let Some(value) = value.downcast_ref::<f32>() {
+        format!("{value:.2}")
+    } else if let Some(value) = value.downcast_ref::<usize>() {
+        value.to_string()
+    } else if let Some(value) = value.downcast_ref::<Option<f32>>() {
+        value.map_or("none".to_string(), |value| format!("{value:.2}"))
+    } else {
+        "?".to_string()
+    }
+}
+
 fn spawn_ui_nodes_rec(
     ui_parent: &mut UiBuilder<Entity>,
     csg_children: &Children,
-    csg_nodes: &Query<(Entity, &Name, &Children), With<CsgNode>>,
-    csg_leafs: &Query<(Entity, &Name), With<CsgLeaf>>,
+    csg_nodes: &Query<(Entity, &Name, &Children, Option<&CsgOp>), With<CsgNode>>,
+    csg_leafs: &Query<(Entity, &Name, Option<&CsgOp>), With<CsgLeaf>>,
 ) {
     for child in csg_children {
-        if let Ok((entity, name, csg_children)) = csg_nodes.get(*child) {
+        if let Ok((entity, name, csg_children, op)) = csg_nodes.get(*child) {
             ui_parent.foldable(name.to_string(), true, |child| {
                 child.insert(CsgRefNode(entity));
+                child
+                    .button(op.copied().unwrap_or_default().symbol())
+                    .insert(CsgOpButton(entity));
                 spawn_ui_nodes_rec(child, csg_children, csg_nodes, csg_leafs);
             });
-        } else if let Ok((entity, name)) = csg_leafs.get(*child) {
-            ui_parent
-                .label(LabelConfig {
+        } else if let Ok((entity, name, op)) = csg_leafs.get(*child) {
+            ui_parent.row(|row| {
+                row.insert(CsgRefNode(entity));
+                row.button(op.copied().unwrap_or_default().symbol())
+                    .insert(CsgOpButton(entity));
+                row.label(LabelConfig {
                     label: name.to_string(),
                     ..default()
-                })
-                .insert(CsgRefNode(entity));
+                });
+            });
         } else {
             continue;
         }
     }
 }
 
+/// Cycle the operation of a csg node when its button is pressed.
+pub fn cycle_csg_op(
+    buttons: Query<(&Interaction, &CsgOpButton, &Children), Changed<Interaction>>,
+    mut csg_ops: Query<&mut CsgOp>,
+    mut labels: Query<&mut Text>,
+) {
+    for (interaction, CsgOpButton(csg_node), children) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Ok(mut op) = csg_ops.get_mut(*csg_node) else {
+            error!("The csg node of an operation button doesn't have a CsgOp.");
+            continue;
+        };
+        *op = op.next();
+        for child in children {
+            if let Ok(mut label) = labels.get_mut(*child) {
+                for section in &mut label.sections {
+                    section.value = op.symbol().to_string();
+                }
+            }
+        }
+    }
+}
+
 /// Must be ran after `setup_csg_root`.
 pub fn set_up_csg_root_ui_link(
     mut commands: Commands,
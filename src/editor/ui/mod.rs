@@ -1,4 +1,11 @@
+use std::marker::PhantomData;
+
 use super::camera;
+use super::csg::{
+    commands::CsgCommandsExt,
+    convert::{Arch, BrushGroup, Pipe, QuarterPipe, Stairs},
+    CsgOp, CsgRoot,
+};
 use crate::prelude::*;
 
 mod hierarchy_view;
@@ -24,11 +31,39 @@ impl Plugin for UiPlugin {
                     .chain()
                     .in_set(UiStartupSet),
             )
+            .add_event::<hierarchy_view::RefreshHierarchyView>()
             .add_systems(PreUpdate, (update_map_focus, exit_app_on_menu_item))
-            .add_systems(Update, view::set_map_view_cam_viewport);
+            .add_systems(
+                Update,
+                (
+                    view::set_map_view_cam_viewport,
+                    hierarchy_view::cycle_csg_op,
+                ),
+            )
+            .add_systems(PostUpdate, hierarchy_view::on_h_ui_refresh);
+        add_brush_group_ui::<Stairs>(app);
+        add_brush_group_ui::<Arch>(app);
+        add_brush_group_ui::<Pipe>(app);
+        add_brush_group_ui::<QuarterPipe>(app);
     }
 }
 
+/// Spawn the groups `G` from the add menu and edit their fields from the hierarchy.
+fn add_brush_group_ui<G: BrushGroup>(app: &mut App) {
+    app.add_systems(PreUpdate, add_brush_group_on_menu_item::<G>)
+        .add_systems(
+            Update,
+            (
+                hierarchy_view::step_brush_group_fields::<G>,
+                hierarchy_view::refresh_on_brush_group_changed::<G>,
+            ),
+        )
+        .add_systems(
+            PostUpdate,
+            hierarchy_view::add_brush_group_fields::<G>.after(hierarchy_view::on_h_ui_refresh),
+        );
+}
+
 #[derive(Component)]
 pub struct UiMainRootNode;
 
@@ -36,6 +71,10 @@ pub struct UiMainRootNode;
 #[reflect(Component)]
 struct ExitAppButton;
 
+/// Menu item spawning a default [`BrushGroup`] `G` in the csg root.
+#[derive(Component)]
+struct AddBrushGroupButton<G: BrushGroup>(PhantomData<G>);
+
 #[derive(Resource, Debug, Default, Reflect)]
 #[reflect(Resource)]
 pub struct IconCache(Vec<Handle<Image>>);
@@ -133,6 +172,36 @@ pub fn setup(
                     .insert(ExitAppButton);
                 },
             );
+
+            row.menu(
+                MenuConfig {
+                    name: "Add".into(),
+                    alt_code: KeyCode::KeyA.into(),
+                },
+                |menu| {
+                    menu.style().background_color(Color::DARK_GRAY);
+                    menu.menu_item(MenuItemConfig {
+                        name: Stairs::NAME.into(),
+                        ..default()
+                    })
+                    .insert(AddBrushGroupButton::<Stairs>(PhantomData));
+                    menu.menu_item(MenuItemConfig {
+                        name: Arch::NAME.into(),
+                        ..default()
+                    })
+                    .insert(AddBrushGroupButton::<Arch>(PhantomData));
+                    menu.menu_item(MenuItemConfig {
+                        name: Pipe::NAME.into(),
+                        ..default()
+                    })
+                    .insert(AddBrushGroupButton::<Pipe>(PhantomData));
+                    menu.menu_item(MenuItemConfig {
+                        name: QuarterPipe::NAME.into(),
+                        ..default()
+                    })
+                    .insert(AddBrushGroupButton::<QuarterPipe>(PhantomData));
+                },
+            );
         });
 
         // The Editor space.
@@ -184,3 +253,21 @@ fn exit_app_on_menu_item(
         exit_app.send(bevy::app::AppExit);
     }
 }
+
+fn add_brush_group_on_menu_item<G: BrushGroup>(
+    mut commands: Commands,
+    q_menu_items: Query<&MenuItem, (With<AddBrushGroupButton<G>>, Changed<MenuItem>)>,
+    q_csg_roots: Query<Entity, With<CsgRoot>>,
+) {
+    let Ok(item) = q_menu_items.get_single() else {
+        return;
+    };
+    if !item.interacted() {
+        return;
+    }
+    let Some(root) = q_csg_roots.iter().next() else {
+        error!("No csg root to add the {} to.", G::NAME);
+        return;
+    };
+    commands.spawn_brush_group(root, CsgOp::Add, G::default());
+}